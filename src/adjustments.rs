@@ -0,0 +1,103 @@
+/// Display-only tweaks applied in the fragment shader. The image data
+/// itself is never touched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adjustments {
+    /// In stops, so each +1 doubles the linear value.
+    pub exposure: f32,
+    pub brightness: f32,
+    pub contrast: f32,
+    pub gamma: f32,
+}
+
+impl Default for Adjustments {
+    fn default() -> Adjustments {
+        Adjustments { exposure: 0.0, brightness: 0.0, contrast: 1.0, gamma: 1.0 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Adjustment {
+    Exposure,
+    Brightness,
+    Contrast,
+    Gamma,
+}
+
+impl Adjustment {
+    pub fn next(self) -> Adjustment {
+        match self {
+            Adjustment::Exposure => Adjustment::Brightness,
+            Adjustment::Brightness => Adjustment::Contrast,
+            Adjustment::Contrast => Adjustment::Gamma,
+            Adjustment::Gamma => Adjustment::Exposure,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Adjustment::Exposure => "exposure",
+            Adjustment::Brightness => "brightness",
+            Adjustment::Contrast => "contrast",
+            Adjustment::Gamma => "gamma",
+        }
+    }
+}
+
+/// How values outside 0 to 1 are brought into range for display, after
+/// exposure. Mostly of use for floating point images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Tonemap {
+    Clamp,
+    Reinhard,
+    Aces,
+}
+
+impl Tonemap {
+    pub fn next(self) -> Tonemap {
+        match self {
+            Tonemap::Clamp => Tonemap::Reinhard,
+            Tonemap::Reinhard => Tonemap::Aces,
+            Tonemap::Aces => Tonemap::Clamp,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tonemap::Clamp => "clamp",
+            Tonemap::Reinhard => "reinhard",
+            Tonemap::Aces => "aces",
+        }
+    }
+}
+
+impl Adjustments {
+    pub fn is_default(&self) -> bool {
+        *self == Adjustments::default()
+    }
+
+    /// Nudges one adjustment by `steps` increments, which may be negative
+    /// or fractional (for smooth scrolling).
+    pub fn step(&mut self, adjustment: Adjustment, steps: f32) {
+        match adjustment {
+            Adjustment::Exposure => self.exposure += 0.25 * steps,
+            Adjustment::Brightness => self.brightness += 0.05 * steps,
+            Adjustment::Contrast => self.contrast = (self.contrast + 0.1 * steps).max(0.0),
+            Adjustment::Gamma => self.gamma = (self.gamma + 0.1 * steps).max(0.1),
+        }
+    }
+
+    pub fn get(&self, adjustment: Adjustment) -> f32 {
+        match adjustment {
+            Adjustment::Exposure => self.exposure,
+            Adjustment::Brightness => self.brightness,
+            Adjustment::Contrast => self.contrast,
+            Adjustment::Gamma => self.gamma,
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        format!("exp {:+.2} bri {:+.2} con {:.2} gam {:.2}",
+            self.exposure, self.brightness, self.contrast, self.gamma)
+    }
+}
@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+
+/// Goes between an archive's path and the name of a file inside it, as in
+/// `comics.cbz!/001.png`.
+const SEPARATOR: &str = "!/";
+
+/// A file inside an archive.
+#[derive(Debug, Clone)]
+pub struct Member {
+    /// Its path inside the archive.
+    pub name: String,
+    pub modified: Option<SystemTime>,
+    pub len: u64,
+    /// Where its data starts in the tar, decompressed if it's compressed,
+    /// or where it is in a zip's directory.
+    offset: u64,
+}
+
+/// What's kept of an archive between reads.
+#[derive(Debug)]
+struct Index {
+    modified: Option<SystemTime>,
+    members: Arc<Vec<Member>>,
+    reader: Mutex<Reader>,
+}
+
+#[derive(Debug)]
+enum Reader {
+    /// The parsed directory, so it isn't read again for every file.
+    Zip(zip::ZipArchive<BufReader<File>>),
+    Tar,
+    /// Compressed tars can only be read forwards, so this is how far in
+    /// the last read left off, to carry on from if the next file is
+    /// further in. Reading through in order decompresses everything once.
+    TarGz(Option<(u64, Box<GzReader>)>),
+}
+
+type GzReader = flate2::bufread::GzDecoder<BufReader<File>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+fn kind(path: &Path) -> Option<Kind> {
+    let name = path.file_name()?.to_str()?.to_lowercase();
+    if name.ends_with(".zip") || name.ends_with(".cbz") {
+        Some(Kind::Zip)
+    } else if name.ends_with(".tar") {
+        Some(Kind::Tar)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(Kind::TarGz)
+    } else {
+        None
+    }
+}
+
+/// Whether `path` names an archive that can be browsed like a directory.
+pub fn is_archive(path: &Path) -> bool {
+    kind(path).is_some()
+}
+
+/// Splits a path to a file inside an archive into the archive's path and
+/// the file's name in it.
+pub fn split(path: &Path) -> Option<(&Path, &str)> {
+    let path = path.to_str()?;
+    path.match_indices(SEPARATOR)
+        .map(|(i, _)| (Path::new(&path[..i]), &path[i + SEPARATOR.len()..]))
+        .find(|(archive, _)| is_archive(archive) && is_file(archive))
+}
+
+/// Whether `path` is a file, remembering the answer since every path that
+/// goes through `split` asks it.
+fn is_file(path: &Path) -> bool {
+    static FILES: OnceLock<Mutex<HashMap<PathBuf, bool>>> = OnceLock::new();
+    *FILES.get_or_init(Default::default).lock().unwrap()
+        .entry(path.to_owned())
+        .or_insert_with(|| path.is_file())
+}
+
+/// Whether `path` is a file inside an archive, which can be read but not
+/// changed.
+pub fn is_member(path: &Path) -> bool {
+    split(path).is_some()
+}
+
+pub fn member_path(archive: &Path, name: &str) -> PathBuf {
+    let mut path = OsString::from(archive.as_os_str());
+    path.push(SEPARATOR);
+    path.push(name);
+    PathBuf::from(path)
+}
+
+/// Reads a whole file into memory, whether it's on disk or in an archive.
+pub fn read(path: &Path) -> std::io::Result<Vec<u8>> {
+    match split(path) {
+        Some((archive, name)) => read_member(archive, name, u64::MAX),
+        None => std::fs::read(path),
+    }
+}
+
+/// Reads no more than the first `limit` bytes of a file, for looking at
+/// its header.
+pub fn read_start(path: &Path, limit: u64) -> std::io::Result<Vec<u8>> {
+    match split(path) {
+        Some((archive, name)) => read_member(archive, name, limit),
+        None => {
+            let mut data = Vec::new();
+            File::open(path)?.take(limit).read_to_end(&mut data)?;
+            Ok(data)
+        },
+    }
+}
+
+/// The file at `path` inside an archive.
+pub fn member(path: &Path) -> std::io::Result<Member> {
+    let (archive, name) = split(path).ok_or_else(|| std::io::Error::other("not in an archive"))?;
+    members(archive)?.iter()
+        .find(|member| member.name == name)
+        .cloned()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no such file in archive"))
+}
+
+/// Every file in an archive, leaving out directories.
+pub fn members(archive: &Path) -> std::io::Result<Arc<Vec<Member>>> {
+    Ok(index(archive)?.members.clone())
+}
+
+/// An archive's listing and what it takes to read files from it, kept
+/// until the archive changes.
+fn index(archive: &Path) -> std::io::Result<Arc<Index>> {
+    static INDEXES: OnceLock<Mutex<HashMap<PathBuf, Arc<Index>>>> = OnceLock::new();
+
+    let modified = std::fs::metadata(archive)?.modified().ok();
+    let indexes = INDEXES.get_or_init(Default::default);
+    if let Some(index) = indexes.lock().unwrap().get(archive) {
+        if index.modified == modified {
+            return Ok(index.clone());
+        }
+    }
+
+    let file = BufReader::new(File::open(archive)?);
+    let (members, reader) = match kind(archive) {
+        Some(Kind::Zip) => {
+            let mut zip = zip::ZipArchive::new(file).map_err(std::io::Error::other)?;
+            (list_zip(&mut zip)?, Reader::Zip(zip))
+        },
+        Some(Kind::Tar) => (list_tar(file)?, Reader::Tar),
+        Some(Kind::TarGz) => (list_tar(flate2::bufread::GzDecoder::new(file))?, Reader::TarGz(None)),
+        None => return Err(std::io::Error::other("not an archive")),
+    };
+    let index = Arc::new(Index { modified, members: Arc::new(members), reader: Mutex::new(reader) });
+    indexes.lock().unwrap().insert(archive.to_owned(), index.clone());
+    Ok(index)
+}
+
+fn list_zip(zip: &mut zip::ZipArchive<impl Read + Seek>) -> std::io::Result<Vec<Member>> {
+    let mut members = Vec::with_capacity(zip.len());
+    for i in 0..zip.len() {
+        // raw, so listing doesn't start decompressing anything
+        let entry = zip.by_index_raw(i).map_err(std::io::Error::other)?;
+        if entry.is_dir() {
+            continue;
+        }
+        members.push(Member {
+            name: entry.name().map_err(std::io::Error::other)?.into_owned(),
+            modified: entry.last_modified().and_then(zip_time),
+            len: entry.size(),
+            offset: i as u64,
+        });
+    }
+    Ok(members)
+}
+
+fn list_tar(reader: impl Read) -> std::io::Result<Vec<Member>> {
+    let mut tar = tar::Archive::new(reader);
+    let mut members = Vec::new();
+    for entry in tar.entries()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        members.push(Member {
+            name: tar_name(&entry)?,
+            modified: entry.header().mtime().ok()
+                .map(|seconds| SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)),
+            len: entry.size(),
+            offset: entry.raw_file_position(),
+        });
+    }
+    Ok(members)
+}
+
+/// Reads up to `limit` bytes from the start of a file in an archive.
+fn read_member(archive: &Path, name: &str, limit: u64) -> std::io::Result<Vec<u8>> {
+    let index = index(archive)?;
+    let member = index.members.iter()
+        .find(|member| member.name == name)
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no such file in archive"))?;
+    let len = member.len.min(limit);
+    let mut data = Vec::with_capacity(len as usize);
+    match &mut *index.reader.lock().unwrap() {
+        Reader::Zip(zip) => {
+            let entry = zip.by_index(member.offset as usize).map_err(std::io::Error::other)?;
+            entry.take(len).read_to_end(&mut data)?;
+        },
+        Reader::Tar => {
+            let mut file = File::open(archive)?;
+            file.seek(SeekFrom::Start(member.offset))?;
+            file.take(len).read_to_end(&mut data)?;
+        },
+        Reader::TarGz(last) => {
+            let (position, mut decoder) = match last.take() {
+                Some((position, decoder)) if position <= member.offset => (position, decoder),
+                // anything further back means starting again
+                _ => (0, Box::new(flate2::bufread::GzDecoder::new(BufReader::new(File::open(archive)?)))),
+            };
+            std::io::copy(&mut (&mut decoder).take(member.offset - position), &mut std::io::sink())?;
+            (&mut decoder).take(len).read_to_end(&mut data)?;
+            if data.len() as u64 == len {
+                *last = Some((member.offset + len, decoder));
+            }
+        },
+    }
+    if data.len() as u64 != len {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(data)
+}
+
+/// An entry's path, without the `./` that tarring a directory from inside
+/// it puts on everything.
+fn tar_name<R: Read>(entry: &tar::Entry<R>) -> std::io::Result<String> {
+    let path = entry.path()?;
+    let name = path.to_string_lossy();
+    Ok(name.strip_prefix("./").unwrap_or(&name).to_owned())
+}
+
+/// Zip times are local with no time zone, so this treats them as UTC,
+/// which is near enough for sorting.
+fn zip_time(time: zip::DateTime) -> Option<SystemTime> {
+    let (year, month, day) = (time.year() as i64, time.month() as i64, time.day() as i64);
+    // days from the epoch to the civil date, shifting the year to start in
+    // March so the leap day comes last
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    let seconds = days * 86400 + time.hour() as i64 * 3600 + time.minute() as i64 * 60 + time.second() as i64;
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(time: Option<SystemTime>) -> u64 {
+        time.unwrap().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    fn zip_seconds(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> u64 {
+        seconds(zip_time(zip::DateTime::from_date_and_time(year, month, day, hour, minute, second).unwrap()))
+    }
+
+    #[test]
+    fn zip_times_count_from_the_epoch() {
+        assert_eq!(zip_seconds(1980, 1, 1, 0, 0, 0), 315532800);
+        assert_eq!(zip_seconds(2000, 1, 31, 23, 59, 58), 949363198);
+        assert_eq!(zip_seconds(2000, 2, 1, 0, 0, 0), 949363200);
+        assert_eq!(zip_seconds(2000, 2, 29, 12, 0, 0), 951825600);
+        assert_eq!(zip_seconds(2023, 12, 31, 23, 59, 58), 1704067198);
+        assert_eq!(zip_seconds(2024, 1, 1, 0, 0, 0), 1704067200);
+    }
+
+    /// A scratch directory, removed again when dropped.
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new(name: &str) -> Scratch {
+            let dir = std::env::temp_dir().join(format!("archive-test-{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Scratch(dir)
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn splits_member_paths_at_the_archive() {
+        let scratch = Scratch::new("split");
+        let archive = scratch.0.join("a.zip");
+        std::fs::write(&archive, b"").unwrap();
+
+        let member = member_path(&archive, "b/c.png");
+        assert_eq!(split(&member), Some((archive.as_path(), "b/c.png")));
+        assert!(is_member(&member));
+        assert_eq!(split(&archive), None);
+        assert!(!is_member(&archive));
+        // only archives that are there count
+        assert_eq!(split(&member_path(&scratch.0.join("missing.zip"), "c.png")), None);
+    }
+
+    fn tar_bytes(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn reads_members_in_any_order() {
+        let scratch = Scratch::new("tar");
+        let tar = tar_bytes(&[("./one.png", b"first"), ("two/three.png", b"second one")]);
+        let plain = scratch.0.join("pages.tar");
+        std::fs::write(&plain, &tar).unwrap();
+        let compressed = scratch.0.join("pages.tgz");
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, &tar).unwrap();
+        std::fs::write(&compressed, encoder.finish().unwrap()).unwrap();
+
+        let zipped = scratch.0.join("pages.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&zipped).unwrap());
+        for (name, data) in [("one.png", &b"first"[..]), ("two/three.png", b"second one")] {
+            zip.start_file(name, zip::write::SimpleFileOptions::default()).unwrap();
+            std::io::Write::write_all(&mut zip, data).unwrap();
+        }
+        zip.finish().unwrap();
+
+        for archive in [&plain, &compressed, &zipped] {
+            let names: Vec<_> = members(archive).unwrap().iter().map(|m| m.name.clone()).collect();
+            assert_eq!(names, ["one.png", "two/three.png"]);
+            // out of order, and then in order again
+            for name in ["two/three.png", "one.png", "one.png", "two/three.png"] {
+                let expected: &[u8] = if name == "one.png" { b"first" } else { b"second one" };
+                assert_eq!(read(&member_path(archive, name)).unwrap(), expected);
+            }
+            assert_eq!(read_start(&member_path(archive, "two/three.png"), 6).unwrap(), b"second");
+            assert!(read(&member_path(archive, "four.png")).is_err());
+        }
+    }
+}
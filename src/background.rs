@@ -0,0 +1,72 @@
+use serde::Deserialize;
+
+use crate::config::Config;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackgroundMode {
+    Checkerboard,
+    Black,
+    White,
+    Custom,
+}
+
+impl BackgroundMode {
+    pub fn next(self) -> BackgroundMode {
+        match self {
+            BackgroundMode::Checkerboard => BackgroundMode::Black,
+            BackgroundMode::Black => BackgroundMode::White,
+            BackgroundMode::White => BackgroundMode::Custom,
+            BackgroundMode::Custom => BackgroundMode::Checkerboard,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Checkerboard {
+    /// Edge length of one square, in window pixels.
+    pub size: f32,
+    pub colors: [[f32; 3]; 2],
+}
+
+/// What shows through transparent parts of the image, and around it.
+#[derive(Debug, Clone, Copy)]
+pub struct Background {
+    pub mode: BackgroundMode,
+    custom: [f32; 3],
+    checkerboard: Checkerboard,
+}
+
+impl Background {
+    pub fn from_config(config: &Config) -> Background {
+        Background {
+            mode: config.background_mode,
+            custom: config.background.0,
+            checkerboard: Checkerboard {
+                size: config.checkerboard_size,
+                colors: [config.checkerboard_colors[0].0, config.checkerboard_colors[1].0],
+            },
+        }
+    }
+
+    pub fn cycle(&mut self) {
+        self.mode = self.mode.next();
+    }
+
+    /// The colour the window is cleared to. In checkerboard mode the
+    /// squares only go behind the image itself.
+    pub fn clear_color(&self) -> [f32; 3] {
+        match self.mode {
+            BackgroundMode::Checkerboard | BackgroundMode::Custom => self.custom,
+            BackgroundMode::Black => [0.0, 0.0, 0.0],
+            BackgroundMode::White => [1.0, 1.0, 1.0],
+        }
+    }
+
+    pub fn checkerboard(&self) -> Option<Checkerboard> {
+        match self.mode {
+            BackgroundMode::Checkerboard => Some(self.checkerboard),
+            _ => None,
+        }
+    }
+}
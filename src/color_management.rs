@@ -0,0 +1,228 @@
+use std::path::Path;
+
+use moxcms::{ColorProfile, DataColorSpace, Layout, ProfileText, TransformOptions};
+
+use crate::config::Config;
+
+/// What images with embedded profiles get converted into, and whether
+/// they're converted at all.
+#[derive(Debug, Clone)]
+pub struct ColorManagement {
+    pub display: DisplayProfile,
+    pub enabled: bool,
+    /// File name endings, before the extension, of images that hold data
+    /// rather than colour.
+    pub data_suffixes: Vec<String>,
+}
+
+impl ColorManagement {
+    pub fn from_config(config: &Config) -> ColorManagement {
+        let display = match &config.display_profile {
+            Some(path) => DisplayProfile::load(path).unwrap_or_else(|e| {
+                eprintln!("failed to load display profile {}: {}", path.display(), e);
+                DisplayProfile::srgb()
+            }),
+            None => DisplayProfile::srgb(),
+        };
+        ColorManagement {
+            display,
+            enabled: config.color_management,
+            data_suffixes: config.data_suffixes.iter().map(|s| s.to_lowercase()).collect(),
+        }
+    }
+
+    /// Whether `path` is named like a normal map, mask or similar, which
+    /// shouldn't be treated as sRGB.
+    pub fn is_data(&self, path: &Path) -> bool {
+        let stem = match path.file_stem() {
+            Some(stem) => stem.to_string_lossy().to_lowercase(),
+            None => return false,
+        };
+        self.data_suffixes.iter().any(|suffix| stem.ends_with(suffix.as_str()))
+    }
+}
+
+/// The profile of the screen, sRGB unless the config points at an ICC file.
+#[derive(Debug, Clone)]
+pub struct DisplayProfile {
+    pub name: String,
+    profile: ColorProfile,
+}
+
+impl DisplayProfile {
+    pub fn srgb() -> DisplayProfile {
+        DisplayProfile { name: "sRGB".to_owned(), profile: ColorProfile::new_srgb() }
+    }
+
+    pub fn load(path: &Path) -> Result<DisplayProfile, Box<dyn std::error::Error>> {
+        let profile = ColorProfile::new_from_slice(&std::fs::read(path)?)?;
+        if profile.color_space != DataColorSpace::Rgb {
+            return Err("display profile isn't RGB".into());
+        }
+        let name = profile_name(&profile)
+            .unwrap_or_else(|| path.file_stem().unwrap_or_default().to_string_lossy().into_owned());
+        Ok(DisplayProfile { name, profile })
+    }
+}
+
+/// An ICC profile found in an image file.
+#[derive(Debug, Clone)]
+pub struct EmbeddedProfile {
+    pub name: String,
+    profile: ColorProfile,
+}
+
+impl EmbeddedProfile {
+    /// Finds and parses the profile in a JPEG, PNG or TIFF file, given its
+    /// contents. Files without one, or in other formats, give `Ok(None)`.
+    pub fn find(data: &[u8]) -> Result<Option<EmbeddedProfile>, Box<dyn std::error::Error>> {
+        let icc = if data.starts_with(&[0xff, 0xd8]) {
+            jpeg_icc(data)
+        } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            png_icc(data)?
+        } else if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+            tiff_icc(data)
+        } else {
+            None
+        };
+        match icc {
+            Some(icc) => Ok(Some(EmbeddedProfile::parse(&icc)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Parses a profile that a decoder has already pulled out of a file.
+    pub fn parse(icc: &[u8]) -> Result<EmbeddedProfile, Box<dyn std::error::Error>> {
+        let profile = ColorProfile::new_from_slice(icc)?;
+        // image already turns CMYK into RGB and gray into RGBA, so only
+        // RGB profiles still describe the decoded samples
+        if profile.color_space != DataColorSpace::Rgb {
+            return Err(format!("{:?} profiles aren't supported", profile.color_space).into());
+        }
+        let name = profile_name(&profile).unwrap_or_else(|| "unnamed profile".to_owned());
+        Ok(EmbeddedProfile { name, profile })
+    }
+
+    pub fn convert_rgba8(&self, img: &image::RgbaImage, display: &DisplayProfile)
+        -> Result<image::RgbaImage, Box<dyn std::error::Error>>
+    {
+        let transform = self.profile.create_transform_8bit(
+            Layout::Rgba, &display.profile, Layout::Rgba, TransformOptions::default())?;
+        let mut converted = img.clone();
+        transform.transform(img, &mut converted)?;
+        Ok(converted)
+    }
+
+    pub fn convert_rgba16(&self, img: &image::ImageBuffer<image::Rgba<u16>, Vec<u16>>, display: &DisplayProfile)
+        -> Result<image::ImageBuffer<image::Rgba<u16>, Vec<u16>>, Box<dyn std::error::Error>>
+    {
+        let transform = self.profile.create_transform_16bit(
+            Layout::Rgba, &display.profile, Layout::Rgba, TransformOptions::default())?;
+        let mut converted = img.clone();
+        transform.transform(img, &mut converted)?;
+        Ok(converted)
+    }
+}
+
+/// The English description of a profile, or the first one it has.
+fn profile_name(profile: &ColorProfile) -> Option<String> {
+    let name = match profile.description.as_ref()? {
+        ProfileText::PlainString(s) => s.clone(),
+        ProfileText::Localizable(strings) => strings.iter()
+            .find(|s| s.language == "en")
+            .or(strings.first())?
+            .value.clone(),
+        ProfileText::Description(description) => description.ascii_string.clone(),
+    };
+    let name = name.trim_end_matches('\0').trim();
+    (!name.is_empty()).then(|| name.to_owned())
+}
+
+/// Joins up the `ICC_PROFILE` APP2 segments, which split profiles over
+/// 64K into numbered chunks.
+fn jpeg_icc(data: &[u8]) -> Option<Vec<u8>> {
+    let mut chunks = Vec::new();
+    let mut pos = 2;
+    while pos + 4 <= data.len() && data[pos] == 0xff {
+        let marker = data[pos + 1];
+        // the image data starts at start-of-scan, and profiles come before it
+        if marker == 0xda || marker == 0xd9 {
+            break;
+        }
+        if marker == 0xff || marker == 0x01 || (0xd0..=0xd7).contains(&marker) {
+            pos += if marker == 0xff { 1 } else { 2 };
+            continue;
+        }
+
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        let segment = data.get(pos + 4..pos + 2 + len)?;
+        if marker == 0xe2 {
+            if let Some(chunk) = segment.strip_prefix(b"ICC_PROFILE\0") {
+                if chunk.len() >= 2 {
+                    chunks.push((chunk[0], &chunk[2..]));
+                }
+            }
+        }
+        pos += 2 + len;
+    }
+
+    if chunks.is_empty() {
+        return None;
+    }
+    chunks.sort_by_key(|&(sequence, _)| sequence);
+    Some(chunks.into_iter().flat_map(|(_, chunk)| chunk.iter().copied()).collect())
+}
+
+/// Inflates the `iCCP` chunk, which holds a name then the compressed profile.
+fn png_icc(data: &[u8]) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+    let mut pos = 8;
+    while pos + 8 <= data.len() {
+        let len = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+        let kind = &data[pos + 4..pos + 8];
+        let chunk = data.get(pos + 8..pos + 8 + len).ok_or("truncated PNG chunk")?;
+        match kind {
+            b"iCCP" => {
+                let name_end = chunk.iter().position(|&b| b == 0).ok_or("bad iCCP chunk")?;
+                // a name, its terminator and the compression method byte
+                let compressed = chunk.get(name_end + 2..).ok_or("bad iCCP chunk")?;
+                let icc = miniz_oxide::inflate::decompress_to_vec_zlib(compressed)
+                    .map_err(|e| format!("bad iCCP chunk: {}", e))?;
+                return Ok(Some(icc));
+            },
+            b"IDAT" | b"IEND" => break,
+            _ => (),
+        }
+        // length, type, data and CRC
+        pos += 12 + len;
+    }
+    Ok(None)
+}
+
+/// Reads the ICC profile tag from the first IFD.
+fn tiff_icc(data: &[u8]) -> Option<Vec<u8>> {
+    const ICC_PROFILE_TAG: u16 = 34675;
+
+    let little_endian = data.starts_with(b"II");
+    let u16_at = |pos: usize| {
+        let bytes = data.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) })
+    };
+    let u32_at = |pos: usize| {
+        let bytes = data.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) } as usize)
+    };
+
+    let ifd = u32_at(4)?;
+    let entries = u16_at(ifd)? as usize;
+    for i in 0..entries {
+        let entry = ifd + 2 + 12 * i;
+        if u16_at(entry)? != ICC_PROFILE_TAG {
+            continue;
+        }
+        // stored as bytes, inline if it fits in the offset field
+        let count = u32_at(entry + 4)?;
+        let offset = if count <= 4 { entry + 8 } else { u32_at(entry + 8)? };
+        return Some(data.get(offset..offset + count)?.to_vec());
+    }
+    None
+}
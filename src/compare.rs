@@ -0,0 +1,115 @@
+/// How the pinned reference image is shown against the current one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareMode {
+    Off,
+    /// Reference on the left half of the window, current on the right.
+    SideBySide,
+    /// Both fill the window, with the reference left of a draggable split.
+    Split,
+    /// The absolute difference between the two.
+    Difference,
+    /// Alternates between the two.
+    Flicker,
+}
+
+impl CompareMode {
+    pub fn next(self) -> CompareMode {
+        match self {
+            CompareMode::Off => CompareMode::SideBySide,
+            CompareMode::SideBySide => CompareMode::Split,
+            CompareMode::Split => CompareMode::Difference,
+            CompareMode::Difference => CompareMode::Flicker,
+            CompareMode::Flicker => CompareMode::Off,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CompareMode::Off => "off",
+            CompareMode::SideBySide => "side by side",
+            CompareMode::Split => "split",
+            CompareMode::Difference => "difference",
+            CompareMode::Flicker => "flicker",
+        }
+    }
+}
+
+/// How close, in window pixels, a click has to be to the split line to
+/// grab it rather than pan.
+pub const SPLIT_GRAB_DISTANCE: i32 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Drag {
+    Pan,
+    Split,
+}
+
+/// How two differently sized images line up in difference and flicker
+/// modes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompareAlign {
+    TopLeft,
+    Center,
+}
+
+impl CompareAlign {
+    pub fn toggle(self) -> CompareAlign {
+        match self {
+            CompareAlign::TopLeft => CompareAlign::Center,
+            CompareAlign::Center => CompareAlign::TopLeft,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CompareAlign::TopLeft => "top left",
+            CompareAlign::Center => "centred",
+        }
+    }
+
+    /// Where the reference's top left corner goes, in pixels of the
+    /// current image.
+    pub fn reference_offset(self, current_size: [i32; 2], reference_size: [i32; 2]) -> [f32; 2] {
+        match self {
+            CompareAlign::TopLeft => [0.0, 0.0],
+            CompareAlign::Center => [
+                ((current_size[0] - reference_size[0]) / 2) as f32,
+                ((current_size[1] - reference_size[1]) / 2) as f32,
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DiffSettings {
+    pub gain: f32,
+    pub heatmap: bool,
+    pub align: CompareAlign,
+}
+
+/// Flips between the reference and the current image `rate` times a second.
+#[derive(Debug, Clone, Copy)]
+pub struct Flicker {
+    pub rate: f32,
+    pub showing_reference: bool,
+    elapsed: f32,
+}
+
+impl Flicker {
+    pub fn new(rate: f32) -> Flicker {
+        Flicker { rate, showing_reference: false, elapsed: 0.0 }
+    }
+
+    /// Returns whether it flipped.
+    pub fn update(&mut self, seconds_elapsed: f32) -> bool {
+        self.elapsed += seconds_elapsed;
+        if self.rate > 0.0 && self.elapsed >= 1.0 / self.rate {
+            self.elapsed = 0.0;
+            self.showing_reference = !self.showing_reference;
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::adjustments::Tonemap;
+use crate::background::BackgroundMode;
+use crate::compare::CompareAlign;
+use crate::hud::HudCorner;
+use crate::keybindings::{self, Action, Bindings, KeyChord};
+
+/// Settings read from `config.toml`. Every field is optional in the file.
+///
+/// ```toml
+/// default_zoom = "fit"      # or a scale, where 1.0 is one screen pixel per image pixel
+/// background = "#1a1a1a"
+/// background_mode = "checkerboard"  # "black", "white" or "custom" for the colour above
+/// checkerboard_size = 8.0
+/// checkerboard_colors = ["#999999", "#666666"]
+/// sort = "modified"         # "name", "modified" or "size"
+/// hud = true
+/// hud_corner = "bottom-left"
+/// flicker_rate = 2.0        # flips per second in flicker compare mode
+/// diff_gain = 4.0
+/// compare_align = "center"  # or "top-left", for images of different sizes
+/// diff_threshold = 0        # channel difference above which a pixel counts as changed
+/// thumbnail_cache = true    # share thumbnails through ~/.cache/thumbnails
+/// tonemap = "aces"          # or "clamp" or "reinhard", for values over 1.0
+/// display_profile = "/usr/share/color/icc/monitor.icc"  # sRGB if not given
+/// color_management = true   # convert images with embedded ICC profiles
+/// data_suffixes = ["_normal", "_mask"]  # images shown as data rather than sRGB colour
+/// svg_dpi = 192.0           # resolution SVGs are first shown at, 96 is their own size
+/// spread_right_to_left = true  # two-page spreads read right to left, as manga do
+/// spread_cover = false      # whether the first image is a cover shown on its own
+///
+/// [keys]
+/// "Ctrl+Right" = "move_right"
+/// "X" = "none"
+///
+/// [gallery_keys]            # looked up before [keys] while the gallery is open
+/// "K" = "grid_up"
+/// "J" = "grid_down"
+///
+/// [strip_keys]              # looked up before [keys] while reading in the strip
+/// "K" = "scroll_up"
+/// "J" = "scroll_down"
+/// "Space" = "scroll_page_down"
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub default_zoom: Zoom,
+    pub background: Color,
+    pub background_mode: BackgroundMode,
+    pub checkerboard_size: f32,
+    pub checkerboard_colors: [Color; 2],
+    pub sort: SortOrder,
+    pub hud: bool,
+    pub hud_corner: HudCorner,
+    pub flicker_rate: f32,
+    pub diff_gain: f32,
+    pub compare_align: CompareAlign,
+    pub diff_threshold: u8,
+    pub thumbnail_cache: bool,
+    pub tonemap: Tonemap,
+    pub display_profile: Option<PathBuf>,
+    pub color_management: bool,
+    pub data_suffixes: Vec<String>,
+    pub svg_dpi: f32,
+    pub spread_right_to_left: bool,
+    pub spread_cover: bool,
+    keys: HashMap<KeyChord, Action>,
+    gallery_keys: HashMap<KeyChord, Action>,
+    strip_keys: HashMap<KeyChord, Action>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            default_zoom: Zoom::Fit,
+            background: Color([0.1, 0.1, 0.1]),
+            background_mode: BackgroundMode::Checkerboard,
+            checkerboard_size: 8.0,
+            checkerboard_colors: [Color([0.6, 0.6, 0.6]), Color([0.4, 0.4, 0.4])],
+            sort: SortOrder::Name,
+            hud: false,
+            hud_corner: HudCorner::TopLeft,
+            flicker_rate: 2.0,
+            diff_gain: 1.0,
+            compare_align: CompareAlign::Center,
+            diff_threshold: 0,
+            thumbnail_cache: true,
+            tonemap: Tonemap::Clamp,
+            display_profile: None,
+            color_management: true,
+            data_suffixes: ["_normal", "_nrm", "_mask", "_roughness", "_metallic", "_height", "_ao"]
+                .map(str::to_owned).to_vec(),
+            svg_dpi: 96.0,
+            spread_right_to_left: false,
+            spread_cover: true,
+            keys: HashMap::new(),
+            gallery_keys: HashMap::new(),
+            strip_keys: HashMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config from `path`, or from the default location if no path
+    /// is given. A missing file at the default location isn't an error.
+    pub fn load(path: Option<&Path>) -> Result<Config, ConfigError> {
+        let path = match path {
+            Some(path) => path.to_owned(),
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| ConfigError { path: path.clone(), message: e.to_string() })?;
+        toml::from_str(&text)
+            .map_err(|e| ConfigError { path, message: e.to_string() })
+    }
+
+    /// The default bindings with the ones from the `[keys]` table layered
+    /// on top. Binding a key to `none` removes its default binding.
+    pub fn bindings(&self) -> Bindings {
+        layered(keybindings::default_bindings(), &self.keys)
+    }
+
+    /// The gallery's own bindings, from the `[gallery_keys]` table. Keys
+    /// not bound here do what they do everywhere else.
+    pub fn gallery_bindings(&self) -> Bindings {
+        layered(keybindings::default_gallery_bindings(), &self.gallery_keys)
+    }
+
+    /// The strip's own bindings, from the `[strip_keys]` table, which work
+    /// the same way.
+    pub fn strip_bindings(&self) -> Bindings {
+        layered(keybindings::default_strip_bindings(), &self.strip_keys)
+    }
+}
+
+fn layered(mut bindings: Bindings, keys: &HashMap<KeyChord, Action>) -> Bindings {
+    for (&chord, &action) in keys.iter() {
+        if action == Action::Unbound {
+            bindings.remove(&chord);
+        } else {
+            bindings.insert(chord, action);
+        }
+    }
+    bindings
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("viewer").join("config.toml"))
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    path: PathBuf,
+    message: String,
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // toml's messages already point at the line and column
+        write!(f, "error in {}: {}", self.path.display(), self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "ZoomRepr")]
+pub enum Zoom {
+    Fit,
+    Scale(f32),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ZoomRepr {
+    Name(String),
+    Scale(f32),
+}
+
+impl TryFrom<ZoomRepr> for Zoom {
+    type Error = String;
+
+    fn try_from(repr: ZoomRepr) -> Result<Zoom, String> {
+        match repr {
+            ZoomRepr::Name(name) if name == "fit" => Ok(Zoom::Fit),
+            ZoomRepr::Scale(scale) if scale > 0.0 => Ok(Zoom::Scale(scale)),
+            _ => Err("default_zoom must be \"fit\" or a positive number".to_owned()),
+        }
+    }
+}
+
+/// An RGB colour, written in the file as `"#rrggbb"`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Color(pub [f32; 3]);
+
+impl std::str::FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Color, String> {
+        let err = || format!("invalid colour {:?}, expected \"#rrggbb\"", s);
+
+        let hex = s.strip_prefix('#').ok_or_else(err)?;
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(err());
+        }
+
+        let mut rgb = [0.0; 3];
+        for (i, c) in rgb.iter_mut().enumerate() {
+            let byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|_| err())?;
+            *c = byte as f32 / 255.0;
+        }
+        Ok(Color(rgb))
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Color, String> {
+        s.parse()
+    }
+}
+
+/// The order images found by scanning a directory are shown in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortOrder {
+    Name,
+    Modified,
+    Size,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glutin::event::{ModifiersState, VirtualKeyCode};
+
+    fn parse(text: &str) -> Result<Config, String> {
+        toml::from_str(text).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn layers_key_tables_over_the_defaults() {
+        let config = parse("[keys]\n\"Ctrl+Right\" = \"next\"\n\"Escape\" = \"none\"\n").unwrap();
+        let bindings = config.bindings();
+        let ctrl_right = KeyChord::new(VirtualKeyCode::Right, ModifiersState::CTRL);
+        assert_eq!(bindings.get(&ctrl_right), Some(&Action::Next));
+        assert_eq!(bindings.get(&"Escape".parse().unwrap()), None);
+        assert_eq!(bindings.get(&"Right".parse().unwrap()), Some(&Action::Next));
+    }
+
+    #[test]
+    fn reports_bad_bindings_with_their_location() {
+        let err = parse("[keys]\n\"Hyper+X\" = \"quit\"\n").unwrap_err();
+        assert!(err.contains("unknown modifier \"Hyper\""), "{}", err);
+        assert!(err.contains("line 2"), "{}", err);
+
+        let err = parse("[strip_keys]\n\"J\" = \"jump\"\n").unwrap_err();
+        assert!(err.contains("unknown action \"jump\""), "{}", err);
+
+        let path = std::env::temp_dir().join(format!("viewer-config-{}.toml", std::process::id()));
+        std::fs::write(&path, "[gallery_keys]\n\"Ctrl+Nope\" = \"grid_up\"\n").unwrap();
+        let err = Config::load(Some(&path)).unwrap_err().to_string();
+        std::fs::remove_file(&path).unwrap();
+        assert!(err.starts_with(&format!("error in {}: ", path.display())), "{}", err);
+        assert!(err.contains("unknown key \"Nope\""), "{}", err);
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use exr::prelude::{FlatSamples, ReadChannels, ReadLayers};
+
+type FloatImage = image::ImageBuffer<image::Rgba<f32>, Vec<f32>>;
+
+/// A group of channels that share a name prefix, like `diffuse.R`,
+/// `diffuse.G` and `diffuse.B`, or a part of a multi-part file.
+#[derive(Debug)]
+pub struct ExrLayer {
+    /// Empty for the unprefixed channels.
+    pub name: String,
+    size: [u32; 2],
+    /// By the part of the name after the prefix, in file order.
+    channels: Vec<(String, Vec<f32>)>,
+    /// Whether any channel was stored as 32 bit rather than half float.
+    full_float: bool,
+}
+
+/// Every layer of an EXR file, decoded to floats.
+#[derive(Debug)]
+pub struct ExrImage {
+    pub layers: Vec<ExrLayer>,
+}
+
+impl ExrImage {
+    /// The size of the first layer, from the start of the file's data.
+    pub fn size(data: &[u8]) -> Result<[u32; 2], Box<dyn std::error::Error>> {
+        let meta = exr::meta::MetaData::read_from_buffered(data, false)?;
+        let header = meta.headers.first().ok_or("EXR file has no parts")?;
+        Ok([header.layer_size.x() as u32, header.layer_size.y() as u32])
+    }
+
+    pub fn read(path: &Path) -> Result<ExrImage, Box<dyn std::error::Error>> {
+        let image = exr::prelude::read()
+            .no_deep_data()
+            .largest_resolution_level()
+            .all_channels()
+            .all_layers()
+            .all_attributes()
+            .from_buffered(std::io::Cursor::new(crate::archive::read(path)?))?;
+
+        let mut layers = Vec::new();
+        for part in image.layer_data.iter() {
+            let part_name = part.attributes.layer_name.as_ref().map(|name| name.to_string());
+            let size = [part.size.x() as u32, part.size.y() as u32];
+
+            let mut groups: BTreeMap<String, ExrLayer> = BTreeMap::new();
+            for channel in part.channel_data.list.iter() {
+                // subsampled channels are rare outside of chroma, and don't
+                // line up with the rest
+                if channel.sampling.x() != 1 || channel.sampling.y() != 1 {
+                    continue;
+                }
+
+                let full_name = channel.name.to_string();
+                let (prefix, suffix) = match full_name.rsplit_once('.') {
+                    Some((prefix, suffix)) => (prefix.to_owned(), suffix.to_owned()),
+                    None => (String::new(), full_name.clone()),
+                };
+                let name = match (&part_name, prefix.is_empty()) {
+                    (Some(part), true) => part.clone(),
+                    (Some(part), false) => format!("{}.{}", part, prefix),
+                    (None, _) => prefix,
+                };
+
+                let layer = groups.entry(name.clone()).or_insert_with(|| ExrLayer {
+                    name, size, channels: Vec::new(), full_float: false,
+                });
+                layer.full_float |= !matches!(channel.sample_data, FlatSamples::F16(_));
+                layer.channels.push((suffix, channel.sample_data.values_as_f32().collect()));
+            }
+
+            layers.extend(groups.into_values());
+        }
+
+        if layers.is_empty() {
+            return Err("no channels in EXR file".into());
+        }
+        Ok(ExrImage { layers })
+    }
+}
+
+/// A layer as RGBA floats, ready for the GPU.
+pub struct LayerPixels {
+    /// The samples as stored, for reading out.
+    pub samples: FloatImage,
+    /// What gets drawn, which differs from the samples for single channel
+    /// layers that have been normalised.
+    pub display: FloatImage,
+    /// Colour channels are linear light, but normalised data isn't.
+    pub linear: bool,
+    pub full_float: bool,
+}
+
+impl ExrLayer {
+    /// The channel names, as "R,G,B,A" or "Z".
+    pub fn channel_names(&self) -> String {
+        self.channels.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join(",")
+    }
+
+    fn channel(&self, names: &[&str]) -> Option<&[f32]> {
+        self.channels.iter()
+            .find(|(name, _)| names.iter().any(|n| name.eq_ignore_ascii_case(n)))
+            .map(|(_, samples)| samples.as_slice())
+    }
+
+    /// Maps the layer's channels to RGBA: R, G, B and A by name if it has
+    /// them, otherwise its first three channels. A layer with just one
+    /// channel, like depth, is shown as grayscale stretched over its range.
+    pub fn to_pixels(&self) -> LayerPixels {
+        let [width, height] = self.size;
+        let count = (width * height) as usize;
+
+        let alpha = self.channel(&["A", "alpha"]);
+        let colour = [
+            self.channel(&["R", "red"]),
+            self.channel(&["G", "green"]),
+            self.channel(&["B", "blue"]),
+        ];
+
+        let (rgb, single): ([Option<&[f32]>; 3], bool) = if colour.iter().any(Option::is_some) {
+            (colour, false)
+        } else {
+            let mut others = self.channels.iter()
+                .map(|(_, samples)| samples.as_slice())
+                .filter(|&samples| alpha.is_none_or(|a| !std::ptr::eq(a, samples)));
+            match (others.next(), others.next(), others.next()) {
+                (Some(only), None, _) => ([Some(only); 3], true),
+                (first, second, third) => ([first, second, third], false),
+            }
+        };
+
+        let mut samples = Vec::with_capacity(count * 4);
+        for i in 0..count {
+            for channel in rgb {
+                samples.push(channel.map_or(0.0, |c| c[i]));
+            }
+            samples.push(alpha.map_or(1.0, |a| a[i]));
+        }
+        let samples = FloatImage::from_raw(width, height, samples).unwrap();
+
+        if !single {
+            return LayerPixels {
+                display: samples.clone(), samples,
+                linear: true, full_float: self.full_float,
+            };
+        }
+
+        // ignore infinities, which depth uses for the background
+        let values = rgb[0].unwrap_or(&[]);
+        let finite = values.iter().copied().filter(|v| v.is_finite());
+        let min = finite.clone().fold(f32::INFINITY, f32::min);
+        let max = finite.fold(f32::NEG_INFINITY, f32::max);
+        let range = if max > min { max - min } else { 1.0 };
+
+        let mut display = samples.clone();
+        for pixel in display.pixels_mut() {
+            let v = pixel.0[0];
+            let v = if v.is_finite() { (v - min) / range } else { 1.0 };
+            pixel.0 = [v, v, v, pixel.0[3]];
+        }
+
+        LayerPixels { samples, display, linear: false, full_float: self.full_float }
+    }
+}
@@ -0,0 +1,521 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+
+use crate::archive;
+use crate::image_list::{FileSignature, ImageEntry};
+use crate::image_renderer::link_program;
+use crate::texture;
+use crate::thumbnail_cache::{Flavor, ThumbnailCache};
+
+/// Thumbnails are generated to fit in a square this size, whatever size
+/// they end up drawn at. It's the freedesktop "large" size, so they can go
+/// in the shared cache.
+pub const THUMBNAIL_SIZE: u32 = 256;
+
+const MAX_ATLAS_SIZE: i32 = 4096;
+
+pub const MIN_CELL_SIZE: f32 = 64.0;
+pub const MAX_CELL_SIZE: f32 = 512.0;
+const PADDING: f32 = 8.0;
+const DOUBLE_CLICK: std::time::Duration = std::time::Duration::from_millis(400);
+
+// rect, then uv rect
+const FLOATS_PER_INSTANCE: usize = 8;
+
+pub type ThumbnailKey = (PathBuf, FileSignature);
+
+/// Scales images down to thumbnails on a background thread, going through
+/// the on-disk cache if there is one. Files that aren't loaded are read
+/// there too.
+#[derive(Debug)]
+pub struct ThumbnailWorker {
+    jobs: Sender<(ThumbnailKey, Option<Arc<image::RgbaImage>>)>,
+    results: Receiver<(ThumbnailKey, image::RgbaImage)>,
+}
+
+impl ThumbnailWorker {
+    pub fn new(cache: Option<ThumbnailCache>) -> ThumbnailWorker {
+        let (jobs, job_receiver) = channel::<(ThumbnailKey, Option<Arc<image::RgbaImage>>)>();
+        let (result_sender, results) = channel();
+
+        std::thread::spawn(move || {
+            for (key, pixels) in job_receiver {
+                // the shared cache is for files other programs can open too
+                let cache = cache.as_ref().filter(|_| !archive::is_member(&key.0));
+                let thumbnail = thumbnail(cache, &key, pixels).unwrap_or_else(|e| {
+                    eprintln!("failed to make a thumbnail of {}: {}", key.0.display(), e);
+                    // an empty one, so it isn't asked for again
+                    image::RgbaImage::new(1, 1)
+                });
+                if result_sender.send((key, thumbnail)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        ThumbnailWorker { jobs, results }
+    }
+
+    pub fn request(&self, key: ThumbnailKey, pixels: Option<Arc<image::RgbaImage>>) {
+        let _ = self.jobs.send((key, pixels));
+    }
+
+    pub fn finished(&self) -> impl Iterator<Item = (ThumbnailKey, image::RgbaImage)> + '_ {
+        self.results.try_iter()
+    }
+}
+
+/// A thumbnail from the cache if it's there, otherwise scaled down from
+/// `pixels`, or from the file if it isn't loaded, and cached.
+fn thumbnail(cache: Option<&ThumbnailCache>, key: &ThumbnailKey, pixels: Option<Arc<image::RgbaImage>>)
+    -> Result<image::RgbaImage, Box<dyn std::error::Error>>
+{
+    let (path, sig) = key;
+    if let Some(thumbnail) = cache.and_then(|cache| cache.load(path, sig, Flavor::Large)) {
+        return Ok(thumbnail);
+    }
+
+    let thumbnail = match pixels {
+        Some(pixels) => make_thumbnail(&pixels, THUMBNAIL_SIZE),
+        None => make_thumbnail(&texture::decode_rgba8(path, Some(THUMBNAIL_SIZE))?, THUMBNAIL_SIZE),
+    };
+    if let Some(cache) = cache {
+        // file managers mostly look for the normal size, so that's filled in too
+        let normal = make_thumbnail(&thumbnail, Flavor::Normal.size());
+        for (flavor, thumbnail) in [(Flavor::Large, &thumbnail), (Flavor::Normal, &normal)] {
+            if let Err(e) = cache.save(path, sig, flavor, thumbnail) {
+                eprintln!("failed to cache thumbnail for {}: {}", path.display(), e);
+                break;
+            }
+        }
+    }
+    Ok(thumbnail)
+}
+
+/// Scales `img` down to fit in a `size` square, keeping its aspect ratio.
+/// Images already smaller are left as they are.
+pub fn make_thumbnail(img: &image::RgbaImage, size: u32) -> image::RgbaImage {
+    let (width, height) = img.dimensions();
+    if width <= size && height <= size {
+        return img.clone();
+    }
+
+    let scale = size as f32 / width.max(height) as f32;
+    let w = ((width as f32 * scale).round() as u32).max(1);
+    let h = ((height as f32 * scale).round() as u32).max(1);
+    image::imageops::thumbnail(img, w, h)
+}
+
+/// One texture holding a grid of thumbnail-sized slots. When it fills
+/// up, thumbnails that aren't on screen get their slots taken.
+#[derive(Debug)]
+struct ThumbnailAtlas {
+    texture: u32,
+    columns: i32,
+    size: i32,
+    slots: Vec<Option<ThumbnailKey>>,
+    lookup: HashMap<ThumbnailKey, (usize, [u32; 2])>,
+}
+
+impl ThumbnailAtlas {
+    fn new() -> ThumbnailAtlas {
+        let mut max_size = 0;
+        unsafe {
+            gl::GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut max_size);
+        }
+        let columns = (max_size.min(MAX_ATLAS_SIZE) / THUMBNAIL_SIZE as i32).max(1);
+        let size = columns * THUMBNAIL_SIZE as i32;
+
+        let texture = crate::texture::create_texture();
+        unsafe {
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as _);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as _);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA as _, size, size,
+                0, gl::RGBA, gl::UNSIGNED_BYTE, std::ptr::null());
+        }
+
+        ThumbnailAtlas {
+            texture, columns, size,
+            slots: vec![None; (columns * columns) as usize],
+            lookup: HashMap::new(),
+        }
+    }
+
+    fn contains(&self, key: &ThumbnailKey) -> bool {
+        self.lookup.contains_key(key)
+    }
+
+    /// How many more thumbnails could go in without pushing out any in
+    /// `keep`.
+    fn room(&self, keep: &HashSet<ThumbnailKey>) -> usize {
+        self.slots.iter()
+            .filter(|slot| slot.as_ref().is_none_or(|key| !keep.contains(key)))
+            .count()
+    }
+
+    fn insert(&mut self, key: ThumbnailKey, thumbnail: &image::RgbaImage, keep: &HashSet<ThumbnailKey>) {
+        let free = self.slots.iter().position(Option::is_none).or_else(|| {
+            self.slots.iter().position(|slot| slot.as_ref().is_some_and(|key| !keep.contains(key)))
+        });
+        let slot = match free {
+            Some(slot) => slot,
+            None => return,
+        };
+
+        if let Some(old) = self.slots[slot].take() {
+            self.lookup.remove(&old);
+        }
+
+        let [x, y] = self.slot_position(slot);
+        let (w, h) = thumbnail.dimensions();
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.texture);
+            gl::TexSubImage2D(gl::TEXTURE_2D, 0, x, y, w as _, h as _,
+                gl::RGBA, gl::UNSIGNED_BYTE, thumbnail.as_ptr() as _);
+        }
+
+        self.slots[slot] = Some(key.clone());
+        self.lookup.insert(key, (slot, [w, h]));
+    }
+
+    fn slot_position(&self, slot: usize) -> [i32; 2] {
+        let slot = slot as i32;
+        let size = THUMBNAIL_SIZE as i32;
+        [(slot % self.columns) * size, (slot / self.columns) * size]
+    }
+
+    /// The thumbnail's size in pixels and its texture coordinate rect.
+    fn get(&self, key: &ThumbnailKey) -> Option<([u32; 2], [f32; 4])> {
+        let &(slot, [w, h]) = self.lookup.get(key)?;
+        let [x, y] = self.slot_position(slot);
+        let size = self.size as f32;
+        Some(([w, h], [x as f32 / size, y as f32 / size, w as f32 / size, h as f32 / size]))
+    }
+}
+
+impl std::ops::Drop for ThumbnailAtlas {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.texture);
+        }
+    }
+}
+
+/// Draws thumbnails out of the atlas, one instance of a unit quad each.
+#[derive(Debug)]
+struct ThumbnailRenderer {
+    program: u32,
+    vertex_array: u32,
+    corner_buffer: u32,
+    instance_buffer: u32,
+
+    instances: Vec<f32>,
+}
+
+impl ThumbnailRenderer {
+    fn new() -> ThumbnailRenderer {
+        let program = link_program(shader_code::VERTEX_SHADER_SOURCE,
+            shader_code::FRAGMENT_SHADER_SOURCE);
+
+        unsafe {
+            gl::UseProgram(program);
+            let location = gl::GetUniformLocation(program, c"atlas".as_ptr());
+            gl::Uniform1i(location, 0);
+
+            let (mut vertex_array, mut corner_buffer, mut instance_buffer) = (0, 0, 0);
+            gl::GenVertexArrays(1, &mut vertex_array);
+            gl::GenBuffers(1, &mut corner_buffer);
+            gl::GenBuffers(1, &mut instance_buffer);
+
+            gl::BindVertexArray(vertex_array);
+
+            let corners: [f32; 8] = [0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0];
+            gl::BindBuffer(gl::ARRAY_BUFFER, corner_buffer);
+            gl::BufferData(gl::ARRAY_BUFFER, std::mem::size_of_val(&corners) as _,
+                corners.as_ptr() as _, gl::STATIC_DRAW);
+            gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, 0, std::ptr::null());
+            gl::EnableVertexAttribArray(0);
+
+            let float_size = std::mem::size_of::<f32>();
+            let stride = (FLOATS_PER_INSTANCE * float_size) as _;
+            gl::BindBuffer(gl::ARRAY_BUFFER, instance_buffer);
+            gl::VertexAttribPointer(1, 4, gl::FLOAT, gl::FALSE, stride, std::ptr::null());
+            gl::EnableVertexAttribArray(1);
+            gl::VertexAttribDivisor(1, 1);
+
+            let ptr = (4 * float_size) as _;
+            gl::VertexAttribPointer(2, 4, gl::FLOAT, gl::FALSE, stride, ptr);
+            gl::EnableVertexAttribArray(2);
+            gl::VertexAttribDivisor(2, 1);
+
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::BindVertexArray(0);
+
+            ThumbnailRenderer {
+                program, vertex_array, corner_buffer, instance_buffer,
+                instances: Vec::new(),
+            }
+        }
+    }
+
+    fn resize_window(&mut self, size: [i32; 2]) {
+        unsafe {
+            gl::UseProgram(self.program);
+            let location = gl::GetUniformLocation(self.program, c"window_size".as_ptr());
+            gl::Uniform2f(location, size[0] as f32, size[1] as f32);
+        }
+    }
+
+    fn queue(&mut self, rect: [f32; 4], uv_rect: [f32; 4]) {
+        self.instances.extend_from_slice(&rect);
+        self.instances.extend_from_slice(&uv_rect);
+    }
+
+    fn flush(&mut self, atlas: u32) {
+        if self.instances.is_empty() {
+            return;
+        }
+
+        unsafe {
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, atlas);
+            gl::UseProgram(self.program);
+
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.instance_buffer);
+            let size = std::mem::size_of_val(self.instances.as_slice()) as _;
+            gl::BufferData(gl::ARRAY_BUFFER, size,
+                self.instances.as_ptr() as _, gl::STREAM_DRAW);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+
+            gl::BindVertexArray(self.vertex_array);
+            let count = (self.instances.len() / FLOATS_PER_INSTANCE) as _;
+            gl::DrawArraysInstanced(gl::TRIANGLE_STRIP, 0, 4, count);
+            gl::BindVertexArray(0);
+
+            gl::Disable(gl::BLEND);
+        }
+
+        self.instances.clear();
+    }
+}
+
+impl std::ops::Drop for ThumbnailRenderer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.corner_buffer);
+            gl::DeleteBuffers(1, &self.instance_buffer);
+            gl::DeleteVertexArrays(1, &self.vertex_array);
+            gl::DeleteProgram(self.program);
+        }
+    }
+}
+
+/// The grid of thumbnails for every image in the list. Thumbnails are
+/// only made for the cells scrolled into view, and made off-thread.
+#[derive(Debug)]
+pub struct Gallery {
+    pub visible: bool,
+    pub cell_size: f32,
+    scroll: f32,
+    window_size: [i32; 2],
+    worker: ThumbnailWorker,
+    pending: HashSet<ThumbnailKey>,
+    atlas: ThumbnailAtlas,
+    renderer: ThumbnailRenderer,
+    last_click: Option<(usize, std::time::Instant)>,
+}
+
+impl Gallery {
+    pub fn new(cache: Option<ThumbnailCache>) -> Gallery {
+        Gallery {
+            visible: false,
+            cell_size: 160.0,
+            scroll: 0.0,
+            window_size: [1, 1],
+            worker: ThumbnailWorker::new(cache),
+            pending: HashSet::new(),
+            atlas: ThumbnailAtlas::new(),
+            renderer: ThumbnailRenderer::new(),
+            last_click: None,
+        }
+    }
+
+    pub fn resize_window(&mut self, size: [i32; 2]) {
+        self.window_size = size;
+        self.renderer.resize_window(size);
+    }
+
+    pub fn columns(&self) -> usize {
+        let cell = self.cell_size + PADDING;
+        ((self.window_size[0] as f32 - PADDING) / cell).floor().max(1.0) as usize
+    }
+
+    /// Where cell `index` is in the window, as x, y, width, height.
+    fn cell_rect(&self, index: usize) -> [f32; 4] {
+        let columns = self.columns();
+        let cell = self.cell_size + PADDING;
+        let x = PADDING + (index % columns) as f32 * cell;
+        let y = PADDING + (index / columns) as f32 * cell - self.scroll;
+        [x, y, self.cell_size, self.cell_size]
+    }
+
+    pub fn index_at(&self, position: [i32; 2], count: usize) -> Option<usize> {
+        let cell = self.cell_size + PADDING;
+        let x = position[0] as f32 - PADDING;
+        let y = position[1] as f32 - PADDING + self.scroll;
+        if x < 0.0 || y < 0.0 || x % cell > self.cell_size || y % cell > self.cell_size {
+            return None;
+        }
+
+        let column = (x / cell) as usize;
+        if column >= self.columns() {
+            return None;
+        }
+        let index = (y / cell) as usize * self.columns() + column;
+        (index < count).then_some(index)
+    }
+
+    fn visible_range(&self, count: usize) -> std::ops::Range<usize> {
+        let cell = self.cell_size + PADDING;
+        let columns = self.columns();
+        let first_row = (self.scroll / cell).floor().max(0.0) as usize;
+        let rows = (self.window_size[1] as f32 / cell).ceil() as usize + 1;
+        (first_row * columns).min(count)..((first_row + rows) * columns).min(count)
+    }
+
+    fn max_scroll(&self, count: usize) -> f32 {
+        let rows = count.div_ceil(self.columns());
+        let height = rows as f32 * (self.cell_size + PADDING) + PADDING;
+        (height - self.window_size[1] as f32).max(0.0)
+    }
+
+    pub fn scroll_by(&mut self, pixels: f32, count: usize) {
+        self.scroll = (self.scroll + pixels).clamp(0.0, self.max_scroll(count));
+    }
+
+    /// Scrolls just far enough to bring cell `index` fully into view.
+    pub fn scroll_to(&mut self, index: usize, count: usize) {
+        let [_, y, _, h] = self.cell_rect(index);
+        if y < PADDING {
+            self.scroll += y - PADDING;
+        } else if y + h + PADDING > self.window_size[1] as f32 {
+            self.scroll += y + h + PADDING - self.window_size[1] as f32;
+        }
+        self.scroll = self.scroll.clamp(0.0, self.max_scroll(count));
+    }
+
+    pub fn set_cell_size(&mut self, size: f32, selected: usize, count: usize) {
+        self.cell_size = size.clamp(MIN_CELL_SIZE, MAX_CELL_SIZE);
+        self.scroll_to(selected, count);
+    }
+
+    /// Records a click on cell `index`, returning whether it was the second
+    /// half of a double click.
+    pub fn click(&mut self, index: usize) -> bool {
+        let now = std::time::Instant::now();
+        let double = matches!(self.last_click,
+            Some((last, time)) if last == index && now - time < DOUBLE_CLICK);
+        self.last_click = if double { None } else { Some((index, now)) };
+        double
+    }
+
+    /// Takes in finished thumbnails and asks for any that are now in view,
+    /// returning whether there's anything new to draw.
+    pub fn update(&mut self, entries: &[ImageEntry]) -> bool {
+        let visible: HashSet<ThumbnailKey> = entries[self.visible_range(entries.len())].iter()
+            .map(|entry| (entry.file.path.clone(), entry.file.sig))
+            .collect();
+
+        let mut redraw = false;
+        for (key, thumbnail) in self.worker.finished() {
+            self.pending.remove(&key);
+            self.atlas.insert(key, &thumbnail, &visible);
+            redraw = true;
+        }
+
+        if self.visible {
+            let mut room = self.atlas.room(&visible).saturating_sub(self.pending.len());
+            for entry in &entries[self.visible_range(entries.len())] {
+                let key = (entry.file.path.clone(), entry.file.sig);
+                if room == 0 {
+                    break;
+                }
+                if self.atlas.contains(&key) || self.pending.contains(&key) {
+                    continue;
+                }
+                self.worker.request(key.clone(), entry.file.loaded().map(|texture| texture.pixels.clone()));
+                self.pending.insert(key);
+                room -= 1;
+            }
+        }
+
+        redraw
+    }
+
+    /// Draws the visible cells, with `selected` highlighted. Cells whose
+    /// thumbnails aren't ready yet get a placeholder.
+    pub fn render(&mut self, text_renderer: &mut crate::text_renderer::TextRenderer,
+        entries: &[ImageEntry], selected: usize)
+    {
+        for index in self.visible_range(entries.len()) {
+            let [x, y, w, h] = self.cell_rect(index);
+            if index == selected {
+                let border = PADDING / 2.0;
+                text_renderer.queue_rect([x - border, y - border],
+                    [w + 2.0 * border, h + 2.0 * border], [0.3, 0.6, 1.0, 0.9]);
+            }
+
+            let file = &entries[index].file;
+            match self.atlas.get(&(file.path.clone(), file.sig)) {
+                Some(([tw, th], uv_rect)) => {
+                    let scale = (w / tw as f32).min(h / th as f32).min(1.0);
+                    let (dw, dh) = (tw as f32 * scale, th as f32 * scale);
+                    self.renderer.queue([x + (w - dw) / 2.0, y + (h - dh) / 2.0, dw, dh], uv_rect);
+                },
+                None => text_renderer.queue_rect([x, y], [w, h], [0.5, 0.5, 0.5, 0.25]),
+            }
+        }
+
+        text_renderer.flush();
+        self.renderer.flush(self.atlas.texture);
+    }
+}
+
+mod shader_code {
+    pub const VERTEX_SHADER_SOURCE: &str =
+        "\
+        #version 330 core\n\
+        layout (location = 0) in vec2 corner;\n\
+        layout (location = 1) in vec4 rect;\n\
+        layout (location = 2) in vec4 uv_rect;\n\
+        \
+        out vec2 vtcoords;\n\
+        \
+        uniform vec2 window_size;\n\
+        \
+        void main() {\n\
+            vec2 pos = rect.xy + corner * rect.zw;\n\
+            vec2 ndc = pos / window_size * 2.0 - 1.0;\n\
+            gl_Position = vec4(ndc.x, -ndc.y, 0.0, 1.0);\n\
+            vtcoords = uv_rect.xy + corner * uv_rect.zw;\n\
+        }\n\
+        \0";
+
+    pub const FRAGMENT_SHADER_SOURCE: &str =
+        "\
+        #version 330 core\n\
+        in vec2 vtcoords;\n\
+        out vec4 fcolor;\n\
+        \
+        uniform sampler2D atlas;\n\
+        \
+        void main() {\n\
+            fcolor = texture(atlas, vtcoords);\n\
+        }\n\
+        \0";
+}
@@ -0,0 +1,114 @@
+use std::path::Path;
+use std::time::Duration;
+
+#[cfg(feature = "heif-sequences")]
+use libheif_rs::HeifErrorCode;
+use libheif_rs::{ColorSpace, HeifContext, Image, LibHeif, RgbChroma};
+
+use crate::texture::{DecodedFrame, DecodedFrames, FrameSamples};
+
+/// Decodes a HEIC or AVIF file through libheif: the primary image for
+/// stills, or every frame of an image sequence with the `heif-sequences`
+/// feature, which needs libheif 1.20. Without it, sequences show their
+/// primary image.
+pub fn read(path: &Path) -> Result<DecodedFrames, Box<dyn std::error::Error>> {
+    let lib_heif = LibHeif::new();
+    let data = crate::archive::read(path)?;
+    let context = HeifContext::read_from_bytes(&data)?;
+
+    #[cfg(feature = "heif-sequences")]
+    if context.has_sequence() {
+        return read_sequence(&context);
+    }
+
+    let handle = context.primary_image_handle()?;
+    let deep = handle.luma_bits_per_pixel() > 8;
+    let image = lib_heif.decode(&handle, color_space(handle.has_alpha_channel(), deep), None)?;
+
+    Ok(DecodedFrames {
+        frames: vec![DecodedFrame { samples: samples(&image)?, duration: Duration::ZERO }],
+        icc: handle.color_profile_raw().map(|profile| profile.data),
+    })
+}
+
+#[cfg(feature = "heif-sequences")]
+fn read_sequence(context: &HeifContext) -> Result<DecodedFrames, Box<dyn std::error::Error>> {
+    let track = context.track_ids().first().and_then(|&id| context.track(id))
+        .ok_or("image sequence has no tracks")?;
+    let timescale = track.timescale().max(1) as f64;
+
+    // the sequence's bit depth isn't known until a frame is decoded, so
+    // they're all asked for at 16 bits, which holds any of them
+    let mut frames = Vec::new();
+    loop {
+        let image = match track.decode_next_image(color_space(true, true), None) {
+            Ok(image) => image,
+            Err(e) if matches!(e.code, HeifErrorCode::EndOfSequence) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let duration = Duration::from_secs_f64(image.duration() as f64 / timescale);
+        frames.push(DecodedFrame { samples: samples(&image)?, duration });
+    }
+
+    Ok(DecodedFrames { frames, icc: None })
+}
+
+/// The size of a HEIC or AVIF file's primary image, from the start of its
+/// data.
+pub fn size(data: &[u8]) -> Result<[u32; 2], Box<dyn std::error::Error>> {
+    let handle = HeifContext::read_from_bytes(data)?.primary_image_handle()?;
+    Ok([handle.width(), handle.height()])
+}
+
+fn color_space(alpha: bool, deep: bool) -> ColorSpace {
+    ColorSpace::Rgb(match (alpha, deep) {
+        (false, false) => RgbChroma::Rgb,
+        (true, false) => RgbChroma::Rgba,
+        (false, true) => RgbChroma::HdrRgbLe,
+        (true, true) => RgbChroma::HdrRgbaLe,
+    })
+}
+
+/// Copies out the interleaved plane, filling in alpha if there isn't any
+/// and stretching deep samples to the full 16 bits.
+fn samples(image: &Image) -> Result<FrameSamples, Box<dyn std::error::Error>> {
+    let planes = image.planes();
+    let plane = planes.interleaved.ok_or("decoded image isn't interleaved")?;
+    let (width, height) = (plane.width, plane.height);
+
+    let channels = match image.color_space() {
+        Some(ColorSpace::Rgb(RgbChroma::Rgba | RgbChroma::HdrRgbaLe)) => 4,
+        _ => 3,
+    };
+    let rows = plane.data.chunks(plane.stride).take(height as usize);
+
+    if plane.storage_bits_per_pixel <= 32 {
+        let mut samples = Vec::with_capacity(width as usize * height as usize * 4);
+        for row in rows {
+            for pixel in row[..width as usize * channels].chunks_exact(channels) {
+                samples.extend_from_slice(&pixel[..3]);
+                samples.push(if channels == 4 { pixel[3] } else { 255 });
+            }
+        }
+        let img = image::ImageBuffer::from_raw(width, height, samples)
+            .ok_or("HEIF image is the wrong size")?;
+        return Ok(FrameSamples::U8(img));
+    }
+
+    // bits_per_pixel is per channel for interleaved planes, storage isn't
+    let max = (1u32 << plane.bits_per_pixel.clamp(1, 16)) - 1;
+    let stretch = |v: u16| (v as u32 * 65535 / max) as u16;
+    let mut samples = Vec::with_capacity(width as usize * height as usize * 4);
+    for row in rows {
+        let row = &row[..width as usize * channels * 2];
+        for pixel in row.chunks_exact(channels * 2) {
+            for c in 0..3 {
+                samples.push(stretch(u16::from_le_bytes([pixel[2 * c], pixel[2 * c + 1]])));
+            }
+            samples.push(if channels == 4 { stretch(u16::from_le_bytes([pixel[6], pixel[7]])) } else { 65535 });
+        }
+    }
+    let img = image::ImageBuffer::from_raw(width, height, samples)
+        .ok_or("HEIF image is the wrong size")?;
+    Ok(FrameSamples::U16(img))
+}
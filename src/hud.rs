@@ -0,0 +1,77 @@
+use crate::text_renderer::TextRenderer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HudCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// The on-screen info overlay, drawn in one corner of the window over a
+/// translucent backdrop.
+#[derive(Debug)]
+pub struct Hud {
+    pub visible: bool,
+    pub corner: HudCorner,
+    pub scale: f32,
+}
+
+const MARGIN: f32 = 8.0;
+const PADDING: f32 = 6.0;
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const BACKDROP_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+
+impl Hud {
+    pub fn new(visible: bool, corner: HudCorner) -> Hud {
+        Hud { visible, corner, scale: 2.0 }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn queue(&self, text_renderer: &mut TextRenderer, lines: &[String], window_size: [i32; 2]) {
+        if !self.visible || lines.is_empty() {
+            return;
+        }
+
+        let text = lines.join("\n");
+        let [text_w, text_h] = TextRenderer::text_size(&text, self.scale);
+        let box_size = [text_w + 2.0 * PADDING, text_h + 2.0 * PADDING];
+
+        let [window_w, window_h] = [window_size[0] as f32, window_size[1] as f32];
+        let left = MARGIN;
+        let right = window_w - MARGIN - box_size[0];
+        let top = MARGIN;
+        let bottom = window_h - MARGIN - box_size[1];
+
+        let pos = match self.corner {
+            HudCorner::TopLeft => [left, top],
+            HudCorner::TopRight => [right, top],
+            HudCorner::BottomLeft => [left, bottom],
+            HudCorner::BottomRight => [right, bottom],
+        };
+
+        text_renderer.queue_rect(pos, box_size, BACKDROP_COLOR);
+        text_renderer.queue_text(&text, [pos[0] + PADDING, pos[1] + PADDING],
+            self.scale, TEXT_COLOR);
+    }
+}
+
+pub fn format_file_size(len: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if len < 1024 {
+        return format!("{} B", len);
+    }
+
+    let mut size = len as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
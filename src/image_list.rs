@@ -0,0 +1,321 @@
+use std::cell::OnceCell;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::archive;
+use crate::config::SortOrder;
+use crate::stats::ImageStats;
+use crate::texture::{self, LoadSettings, Texture};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileSignature {
+    modified: Option<std::time::SystemTime>,
+    created: Option<std::time::SystemTime>,
+    pub len: u64,
+}
+
+impl FileSignature {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<FileSignature, Box<dyn std::error::Error>> {
+        if let Some((archive, _)) = archive::split(path.as_ref()) {
+            let member = archive::member(path.as_ref())?;
+            let mdata = std::fs::metadata(archive)?;
+            return Ok(FileSignature {
+                modified: member.modified.or(mdata.modified().ok()),
+                created: mdata.created().ok(),
+                len: member.len,
+            });
+        }
+
+        let mdata = std::fs::metadata(path)?;
+        Ok(FileSignature {
+            modified: mdata.modified().ok(),
+            created: mdata.created().ok(),
+            len: mdata.len(),
+        })
+    }
+
+    /// Modification time in whole seconds since the epoch.
+    pub fn mtime(&self) -> Option<u64> {
+        let modified = self.modified?;
+        Some(modified.duration_since(std::time::UNIX_EPOCH).ok()?.as_secs())
+    }
+}
+
+/// A file in the list, which is only decoded while it's needed.
+#[derive(Debug)]
+pub struct TextureFile {
+    texture: Option<Texture>,
+    /// The image's size while it isn't loaded, read when it's first asked
+    /// for or kept from when it was.
+    size: OnceCell<[i32; 2]>,
+    pub path: PathBuf,
+    pub sig: FileSignature,
+    pub stats: Option<Arc<ImageStats>>,
+    /// Which of the file's images is loaded, for multi-page TIFFs, icons
+    /// with several sizes and the like. The count is the texture's.
+    pub page: usize,
+}
+
+impl TextureFile {
+    pub fn load(path: &Path, settings: &LoadSettings) -> Result<TextureFile, Box<dyn std::error::Error>> {
+        let mut file = TextureFile::unloaded(path)?;
+        file.texture = Some(Texture::from_file(path, settings)?);
+        Ok(file)
+    }
+
+    /// Knows about a file without reading any more of it than it takes to
+    /// tell when it changes.
+    pub fn unloaded(path: &Path) -> Result<TextureFile, Box<dyn std::error::Error>> {
+        let sig = FileSignature::new(path)?;
+        Ok(TextureFile { texture: None, size: OnceCell::new(), path: path.to_owned(), sig, stats: None, page: 0 })
+    }
+
+    /// The loaded image. Only files that have been through `ensure_loaded`
+    /// can be shown, so anything else is a bug.
+    pub fn texture(&self) -> &Texture {
+        self.texture.as_ref().expect("shown files are loaded")
+    }
+
+    pub fn texture_mut(&mut self) -> &mut Texture {
+        self.texture.as_mut().expect("shown files are loaded")
+    }
+
+    pub fn loaded(&self) -> Option<&Texture> {
+        self.texture.as_ref()
+    }
+
+    pub fn loaded_mut(&mut self) -> Option<&mut Texture> {
+        self.texture.as_mut()
+    }
+
+    /// Decodes the file if it isn't already, returning whether it had to.
+    /// Files that can't be read are shown blank rather than not at all.
+    pub fn ensure_loaded(&mut self, settings: &LoadSettings) -> bool {
+        if self.texture.is_some() {
+            return false;
+        }
+        let texture = Texture::from_page(&self.path, self.page, settings).unwrap_or_else(|e| {
+            eprintln!("failed to load {}: {}", self.path.display(), e);
+            Texture::blank()
+        });
+        self.texture = Some(texture);
+        true
+    }
+
+    /// Lets go of the decoded image, keeping its size and stats.
+    pub fn unload(&mut self) {
+        if let Some(texture) = self.texture.take() {
+            self.size = OnceCell::from(texture.size);
+        }
+    }
+
+    /// The image's size, without loading it if it isn't already.
+    pub fn size(&self, settings: &LoadSettings) -> [i32; 2] {
+        if let Some(texture) = &self.texture {
+            return texture.size;
+        }
+        *self.size.get_or_init(|| texture::probe_size(&self.path, settings).unwrap_or_else(|e| {
+            eprintln!("failed to read the size of {}: {}", self.path.display(), e);
+            [1, 1]
+        }))
+    }
+
+    /// Swaps the loaded image for another page of the same file.
+    pub fn set_page(&mut self, page: usize, settings: &LoadSettings) -> Result<(), Box<dyn std::error::Error>> {
+        self.texture = Some(Texture::from_page(&self.path, page, settings)?);
+        self.page = page;
+        self.stats = None;
+        Ok(())
+    }
+}
+
+/// The two halves of a paired-directory review.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Baseline,
+    Candidate,
+}
+
+impl Side {
+    pub fn name(self) -> &'static str {
+        match self {
+            Side::Baseline => "baseline",
+            Side::Candidate => "candidate",
+        }
+    }
+}
+
+/// One entry in the list being viewed. Usually that's a single file, but
+/// when reviewing paired directories it's a candidate along with the
+/// baseline it should match.
+#[derive(Debug)]
+pub struct ImageEntry {
+    /// The candidate when paired, or whichever side exists if the other
+    /// one is missing.
+    pub file: TextureFile,
+    pub baseline: Option<TextureFile>,
+    pub missing: Option<Side>,
+    /// Where the pair sits under both directories.
+    pub relative_path: Option<PathBuf>,
+}
+
+impl ImageEntry {
+    /// What the entry is known by in a review: the relative path for a
+    /// pair, otherwise the path as given.
+    pub fn key(&self) -> String {
+        let path = self.relative_path.as_ref().unwrap_or(&self.file.path);
+        path.to_string_lossy().into_owned()
+    }
+
+    pub fn files_mut(&mut self) -> impl Iterator<Item = &mut TextureFile> {
+        std::iter::once(&mut self.file).chain(self.baseline.iter_mut())
+    }
+}
+
+/// The paths making up an `ImageEntry`, before anything is loaded.
+#[derive(Debug, Clone)]
+pub struct EntryPaths {
+    pub path: PathBuf,
+    pub baseline: Option<PathBuf>,
+    pub missing: Option<Side>,
+    pub relative_path: Option<PathBuf>,
+}
+
+impl EntryPaths {
+    pub fn single(path: PathBuf) -> EntryPaths {
+        EntryPaths { path, baseline: None, missing: None, relative_path: None }
+    }
+
+    /// The entry's files, which are loaded when they're first shown.
+    pub fn entry(&self) -> Result<ImageEntry, Box<dyn std::error::Error>> {
+        let baseline = match &self.baseline {
+            Some(path) => Some(TextureFile::unloaded(path)?),
+            None => None,
+        };
+
+        Ok(ImageEntry {
+            file: TextureFile::unloaded(&self.path)?,
+            baseline,
+            missing: self.missing,
+            relative_path: self.relative_path.clone(),
+        })
+    }
+}
+
+pub fn is_image_path(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("png" | "jpg" | "bmp" | "gif" | "jpeg" | "tif" | "tiff" | "hdr" | "exr" | "svg" | "svgz" | "ico" | "psd") => true,
+        #[cfg(feature = "avif")]
+        Some("avif") => true,
+        #[cfg(feature = "heic")]
+        Some("heic" | "heif") => true,
+        #[cfg(feature = "jxl")]
+        Some("jxl") => true,
+        _ => false,
+    }
+}
+
+pub fn all_images_in_directory<P: AsRef<Path>>(dir: P, sort: SortOrder)
+    -> std::io::Result<Vec<PathBuf>>
+{
+    let mut paths = Vec::new();
+
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if is_image_path(&entry.path()) {
+            paths.push(entry.path().clone());
+        }
+    }
+
+    match sort {
+        SortOrder::Name => paths.sort(),
+        SortOrder::Modified => paths.sort_by_cached_key(|p| {
+            std::fs::metadata(p).and_then(|m| m.modified()).ok()
+        }),
+        SortOrder::Size => paths.sort_by_cached_key(|p| {
+            std::fs::metadata(p).map(|m| m.len()).ok()
+        }),
+    }
+
+    Ok(paths)
+}
+
+/// The images inside an archive, as paths through it, without reading
+/// any of them.
+pub fn all_images_in_archive(archive: &Path, sort: SortOrder) -> std::io::Result<Vec<PathBuf>> {
+    let mut members: Vec<_> = archive::members(archive)?.iter()
+        .filter(|member| is_image_path(Path::new(&member.name)))
+        .cloned()
+        .collect();
+
+    match sort {
+        SortOrder::Name => members.sort_by(|a, b| Path::new(&a.name).cmp(Path::new(&b.name))),
+        SortOrder::Modified => members.sort_by_key(|member| member.modified),
+        SortOrder::Size => members.sort_by_key(|member| member.len),
+    }
+
+    Ok(members.iter().map(|member| archive::member_path(archive, &member.name)).collect())
+}
+
+/// The images at `paths`, with directories and archives swapped for the
+/// images in them.
+pub fn expand_paths(paths: &[PathBuf], sort: SortOrder) -> std::io::Result<Vec<PathBuf>> {
+    let mut image_paths = Vec::new();
+    for p in paths.iter() {
+        if p.is_dir() {
+            image_paths.append(&mut all_images_in_directory(p, sort)?);
+        } else if archive::is_archive(p) {
+            image_paths.append(&mut all_images_in_archive(p, sort)?);
+        } else {
+            image_paths.push(p.clone());
+        }
+    }
+    Ok(image_paths)
+}
+
+/// Every image under `root`, however deep, relative to `root`.
+fn images_in_tree(root: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    let mut dirs = vec![root.to_owned()];
+
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if is_image_path(&path) {
+                if let Ok(relative) = path.strip_prefix(root) {
+                    paths.push(relative.to_owned());
+                }
+            }
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Matches up the images under two directory trees by their path relative
+/// to each root, in path order. Files only found on one side are kept,
+/// marked as missing the other.
+pub fn paired_paths(baseline_dir: &Path, candidate_dir: &Path)
+    -> std::io::Result<Vec<EntryPaths>>
+{
+    let baselines: BTreeSet<_> = images_in_tree(baseline_dir)?.into_iter().collect();
+    let candidates: BTreeSet<_> = images_in_tree(candidate_dir)?.into_iter().collect();
+
+    let entries = baselines.union(&candidates).map(|relative| {
+        let baseline = baseline_dir.join(relative);
+        let candidate = candidate_dir.join(relative);
+
+        let (path, baseline, missing) = match (baselines.contains(relative), candidates.contains(relative)) {
+            (true, true) => (candidate, Some(baseline), None),
+            (true, false) => (baseline, None, Some(Side::Candidate)),
+            _ => (candidate, None, Some(Side::Baseline)),
+        };
+
+        EntryPaths { path, baseline, missing, relative_path: Some(relative.clone()) }
+    }).collect();
+
+    Ok(entries)
+}
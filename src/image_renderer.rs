@@ -1,4 +1,6 @@
-use crate::texture::{Texture, create_texture};
+use crate::adjustments::{Adjustments, Tonemap};
+use crate::background::Checkerboard;
+use crate::texture::{Encoding, Texture, create_texture};
 
 pub trait Renderer {
     fn render(&self);
@@ -6,15 +8,99 @@ pub trait Renderer {
     fn set_translate(&mut self, translate: [f32;2]);
 }
 
+/// Which part of the image's colour gets shown. The isolation modes show
+/// their channel as an opaque grayscale image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayMode {
+    Normal,
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    Luminance,
+    IgnoreAlpha,
+}
+
+impl DisplayMode {
+    pub fn next(self) -> DisplayMode {
+        match self {
+            DisplayMode::Normal => DisplayMode::Red,
+            DisplayMode::Red => DisplayMode::Green,
+            DisplayMode::Green => DisplayMode::Blue,
+            DisplayMode::Blue => DisplayMode::Alpha,
+            DisplayMode::Alpha => DisplayMode::Luminance,
+            DisplayMode::Luminance => DisplayMode::IgnoreAlpha,
+            DisplayMode::IgnoreAlpha => DisplayMode::Normal,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DisplayMode::Normal => "rgba",
+            DisplayMode::Red => "red",
+            DisplayMode::Green => "green",
+            DisplayMode::Blue => "blue",
+            DisplayMode::Alpha => "alpha",
+            DisplayMode::Luminance => "luminance",
+            DisplayMode::IgnoreAlpha => "rgb",
+        }
+    }
+
+    // must match the display_mode checks in the fragment shader
+    fn shader_value(self) -> i32 {
+        match self {
+            DisplayMode::Normal => 0,
+            DisplayMode::Red => 1,
+            DisplayMode::Green => 2,
+            DisplayMode::Blue => 3,
+            DisplayMode::Alpha => 4,
+            DisplayMode::Luminance => 5,
+            DisplayMode::IgnoreAlpha => 6,
+        }
+    }
+}
+
+// must match the encoding checks in the fragment shader
+fn encoding_shader_value(encoding: Encoding) -> i32 {
+    match encoding {
+        Encoding::Linear => 0,
+        Encoding::Srgb => 1,
+        Encoding::Data => 2,
+    }
+}
+
+/// What to do with a second image drawn through the first one's quad.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReferenceOp {
+    /// Absolute difference, multiplied by `gain`, optionally as a heatmap.
+    Difference { gain: f32, heatmap: bool },
+    /// Show the reference instead, pixel aligned to the main image.
+    Show,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReferenceView {
+    pub texture_id: u32,
+    pub size: [i32; 2],
+    /// Where the reference's top left corner sits, in main image pixels.
+    pub offset: [f32; 2],
+    pub encoding: Encoding,
+    pub op: ReferenceOp,
+}
+
 #[derive(Debug)]
 pub struct ImageRenderer {
     program: u32,
     vertex_array: u32,
     buffer: u32,
     texture: u32,
+    reference_texture: Option<u32>,
 
     texture_loaded: bool,
     texture_size: [i32; 2],
+    // whether the framebuffer encodes to sRGB itself, so the shader
+    // should output linear light
+    srgb_framebuffer: bool,
 }
 
 impl ImageRenderer {
@@ -25,12 +111,16 @@ impl ImageRenderer {
 
         let mut r = ImageRenderer {
             program, vertex_array, buffer, texture,
+            reference_texture: None,
             texture_loaded: false,
             texture_size: [0, 0],
+            srgb_framebuffer: false,
         };
 
         r.set_scale([1.0, 1.0]);
         r.set_translate([0.0, 0.0]);
+        r.set_adjustments(Adjustments::default());
+        r.set_tonemap(Tonemap::Clamp);
 
         r
     }
@@ -40,12 +130,129 @@ impl ImageRenderer {
         self.texture_size = texture.size;
         self.texture_loaded = true;
 
+        unsafe {
+            gl::UseProgram(self.program);
+            let location = gl::GetUniformLocation(self.program,
+                c"image_size".as_ptr());
+            gl::Uniform2f(location, texture.size[0] as f32, texture.size[1] as f32);
+            let location = gl::GetUniformLocation(self.program,
+                c"input_encoding".as_ptr());
+            gl::Uniform1i(location, encoding_shader_value(texture.encoding()));
+        }
+
         Ok(())
     }
 
+    pub fn set_srgb_framebuffer(&mut self, srgb: bool) {
+        self.srgb_framebuffer = srgb;
+        unsafe {
+            gl::UseProgram(self.program);
+            let location = gl::GetUniformLocation(self.program, c"srgb_framebuffer".as_ptr());
+            gl::Uniform1i(location, srgb as _);
+        }
+    }
+
     pub fn get_image_size(&self) -> [i32; 2] {
         self.texture_size
     }
+
+    pub fn set_display_mode(&mut self, mode: DisplayMode) {
+        unsafe {
+            gl::UseProgram(self.program);
+            let location = gl::GetUniformLocation(self.program,
+                c"display_mode".as_ptr());
+            gl::Uniform1i(location, mode.shader_value());
+        }
+    }
+
+    pub fn set_adjustments(&mut self, adjustments: Adjustments) {
+        unsafe {
+            gl::UseProgram(self.program);
+            let uniforms = [
+                (c"exposure", adjustments.exposure),
+                (c"brightness", adjustments.brightness),
+                (c"contrast", adjustments.contrast),
+                (c"gamma", adjustments.gamma),
+            ];
+            for (name, value) in uniforms {
+                let location = gl::GetUniformLocation(self.program, name.as_ptr());
+                gl::Uniform1f(location, value);
+            }
+        }
+    }
+
+    pub fn set_tonemap(&mut self, tonemap: Tonemap) {
+        // must match the tonemap checks in the fragment shader
+        let value = match tonemap {
+            Tonemap::Clamp => 0,
+            Tonemap::Reinhard => 1,
+            Tonemap::Aces => 2,
+        };
+        unsafe {
+            gl::UseProgram(self.program);
+            let location = gl::GetUniformLocation(self.program, c"tonemap".as_ptr());
+            gl::Uniform1i(location, value);
+        }
+    }
+
+    pub fn set_reference_view(&mut self, view: Option<ReferenceView>) {
+        self.reference_texture = view.map(|v| v.texture_id);
+
+        unsafe {
+            gl::UseProgram(self.program);
+            let op = match view.map(|v| v.op) {
+                None => 0,
+                Some(ReferenceOp::Difference { .. }) => 1,
+                Some(ReferenceOp::Show) => 2,
+            };
+            let location = gl::GetUniformLocation(self.program, c"reference_op".as_ptr());
+            gl::Uniform1i(location, op);
+
+            let view = match view {
+                Some(view) => view,
+                None => return,
+            };
+
+            let location = gl::GetUniformLocation(self.program, c"reference_size".as_ptr());
+            gl::Uniform2f(location, view.size[0] as f32, view.size[1] as f32);
+            let location = gl::GetUniformLocation(self.program, c"reference_offset".as_ptr());
+            gl::Uniform2f(location, view.offset[0], view.offset[1]);
+            let location = gl::GetUniformLocation(self.program, c"reference_encoding".as_ptr());
+            gl::Uniform1i(location, encoding_shader_value(view.encoding));
+
+            if let ReferenceOp::Difference { gain, heatmap } = view.op {
+                let location = gl::GetUniformLocation(self.program, c"diff_gain".as_ptr());
+                gl::Uniform1f(location, gain);
+                let location = gl::GetUniformLocation(self.program, c"diff_heatmap".as_ptr());
+                gl::Uniform1i(location, heatmap as _);
+            }
+        }
+    }
+
+    /// Composites the image over a checkerboard, or over whatever is already
+    /// in the framebuffer when `None`.
+    pub fn set_checkerboard(&mut self, checkerboard: Option<Checkerboard>) {
+        unsafe {
+            gl::UseProgram(self.program);
+            let location = gl::GetUniformLocation(self.program,
+                c"checkerboard".as_ptr());
+            gl::Uniform1i(location, checkerboard.is_some() as _);
+
+            if let Some(checkerboard) = checkerboard {
+                let location = gl::GetUniformLocation(self.program,
+                    c"checker_size".as_ptr());
+                gl::Uniform1f(location, checkerboard.size);
+
+                let [a, b] = checkerboard.colors;
+                let location = gl::GetUniformLocation(self.program,
+                    c"checker_color_a".as_ptr());
+                gl::Uniform3f(location, a[0], a[1], a[2]);
+                let location = gl::GetUniformLocation(self.program,
+                    c"checker_color_b".as_ptr());
+                gl::Uniform3f(location, b[0], b[1], b[2]);
+            }
+        }
+    }
 }
 
 impl std::ops::Drop for ImageRenderer {
@@ -62,13 +269,30 @@ impl Renderer for ImageRenderer {
     fn render(&self) {
         if self.texture_loaded {
             unsafe {
+                if let Some(reference_texture) = self.reference_texture {
+                    gl::ActiveTexture(gl::TEXTURE1);
+                    gl::BindTexture(gl::TEXTURE_2D, reference_texture);
+                }
+
                 gl::ActiveTexture(gl::TEXTURE0);
                 gl::BindTexture(gl::TEXTURE_2D, self.texture);
 
                 gl::UseProgram(self.program);
 
+                // straight (not premultiplied) alpha, as decoded
+                gl::Enable(gl::BLEND);
+                gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+                // so blending happens in linear light. Only images get
+                // this, everything else draws sRGB values as they are
+                if self.srgb_framebuffer {
+                    gl::Enable(gl::FRAMEBUFFER_SRGB);
+                }
+
                 gl::BindVertexArray(self.vertex_array);
                 gl::DrawArrays(gl::TRIANGLE_FAN, 0, 4);
+
+                gl::Disable(gl::FRAMEBUFFER_SRGB);
+                gl::Disable(gl::BLEND);
             }
         }
     }
@@ -77,7 +301,7 @@ impl Renderer for ImageRenderer {
         unsafe {
             gl::UseProgram(self.program);
             let location = gl::GetUniformLocation(self.program,
-                b"scale\0".as_ptr() as _);
+                c"scale".as_ptr());
             gl::Uniform2f(location, scale[0], scale[1]);
         }
     }
@@ -86,7 +310,7 @@ impl Renderer for ImageRenderer {
         unsafe {
             gl::UseProgram(self.program);
             let location = gl::GetUniformLocation(self.program,
-                b"translate\0".as_ptr() as _);
+                c"translate".as_ptr());
             gl::Uniform2f(location, translate[0], translate[1]);
         }
     }
@@ -143,10 +367,10 @@ fn create_vertex_array() -> BufferData {
     }
 }
 
-fn create_program() -> u32 {
+pub fn link_program(vertex_source: &str, fragment_source: &str) -> u32 {
     unsafe {
-        let vshader = compile_shader(shader_code::VERTEX_SHADER_SOURCE, gl::VERTEX_SHADER);
-        let fshader = compile_shader(shader_code::FRAGMENT_SHADER_SOURCE, gl::FRAGMENT_SHADER);
+        let vshader = compile_shader(vertex_source, gl::VERTEX_SHADER);
+        let fshader = compile_shader(fragment_source, gl::FRAGMENT_SHADER);
 
         let program = gl::CreateProgram();
         gl::AttachShader(program, vshader);
@@ -156,11 +380,23 @@ fn create_program() -> u32 {
         gl::DeleteShader(vshader);
         gl::DeleteShader(fshader);
 
+        program
+    }
+}
+
+fn create_program() -> u32 {
+    unsafe {
+        let program = link_program(shader_code::VERTEX_SHADER_SOURCE,
+            shader_code::FRAGMENT_SHADER_SOURCE);
+
         gl::UseProgram(program);
-        let location = gl::GetUniformLocation(program, "texture1\0".as_ptr() as _); 
+        let location = gl::GetUniformLocation(program, c"texture1".as_ptr()); 
         gl::Uniform1i(location, 0);
 
-        let location = gl::GetUniformLocation(program, "aspect_ratio\0".as_ptr() as _);
+        let location = gl::GetUniformLocation(program, c"reference_texture".as_ptr());
+        gl::Uniform1i(location, 1);
+
+        let location = gl::GetUniformLocation(program, c"aspect_ratio".as_ptr());
         gl::Uniform1f(location, 1.0);
 
         program
@@ -216,9 +452,120 @@ mod shader_code {
         out vec4 fcolor;\n\
         \
         uniform sampler2D texture1;\n\
+        uniform sampler2D reference_texture;\n\
+        uniform int reference_op;\n\
+        uniform vec2 image_size;\n\
+        uniform vec2 reference_size;\n\
+        uniform vec2 reference_offset;\n\
+        uniform float diff_gain;\n\
+        uniform bool diff_heatmap;\n\
+        uniform int display_mode;\n\
+        uniform float exposure;\n\
+        uniform float brightness;\n\
+        uniform float contrast;\n\
+        uniform float gamma;\n\
+        uniform int tonemap;\n\
+        uniform int input_encoding;\n\
+        uniform int reference_encoding;\n\
+        uniform bool srgb_framebuffer;\n\
+        uniform bool checkerboard;\n\
+        uniform float checker_size;\n\
+        uniform vec3 checker_color_a;\n\
+        uniform vec3 checker_color_b;\n\
+        \
+        vec3 heatmap(float t) {\n\
+            t = clamp(t, 0.0, 1.0);\n\
+            return clamp(vec3(3.0 * t, 3.0 * t - 1.0, 3.0 * t - 2.0), 0.0, 1.0);\n\
+        }\n\
+        \
+        vec3 linear_to_srgb(vec3 c) {\n\
+            vec3 low = c * 12.92;\n\
+            vec3 high = 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055;\n\
+            return mix(low, high, step(vec3(0.0031308), c));\n\
+        }\n\
+        \
+        vec3 srgb_to_linear(vec3 c) {\n\
+            vec3 low = c / 12.92;\n\
+            vec3 high = pow((c + 0.055) / 1.055, vec3(2.4));\n\
+            return mix(low, high, step(vec3(0.04045), c));\n\
+        }\n\
+        \
+        vec4 read_image(sampler2D tex, vec2 coords, int encoding) {\n\
+            vec4 c = texture(tex, coords);\n\
+            if (encoding == 1) {\n\
+                c.rgb = srgb_to_linear(clamp(c.rgb, 0.0, 1.0));\n\
+            }\n\
+            return c;\n\
+        }\n\
+        \
+        vec4 to_display(vec4 c, int encoding) {\n\
+            return encoding == 2 ? c : vec4(linear_to_srgb(max(c.rgb, 0.0)), c.a);\n\
+        }\n\
+        \
+        vec3 aces(vec3 x) {\n\
+            return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);\n\
+        }\n\
         \
         void main() {\n\
-            fcolor = texture(texture1, vtcoords);\n\
+            vec4 color = read_image(texture1, vtcoords, input_encoding);\n\
+            int encoding = input_encoding;\n\
+            if (reference_op != 0) {\n\
+                vec2 rcoords = (vtcoords * image_size - reference_offset) / reference_size;\n\
+                bool inside = all(greaterThanEqual(rcoords, vec2(0.0)))\n\
+                    && all(lessThan(rcoords, vec2(1.0)));\n\
+                vec4 reference = inside\n\
+                    ? read_image(reference_texture, rcoords, reference_encoding) : vec4(0.0);\n\
+                if (reference_op == 1) {\n\
+                    vec4 diff = inside ? abs(to_display(color, encoding)\n\
+                        - to_display(reference, reference_encoding)) * diff_gain : vec4(1.0);\n\
+                    float peak = max(max(diff.r, diff.g), max(diff.b, diff.a));\n\
+                    color = vec4(diff_heatmap ? heatmap(peak) : diff.rgb, 1.0);\n\
+                    encoding = 2;\n\
+                } else {\n\
+                    color = reference;\n\
+                    encoding = reference_encoding;\n\
+                }\n\
+            }\n\
+            color.rgb *= exp2(exposure);\n\
+            color.rgb = max(color.rgb, 0.0);\n\
+            if (tonemap == 1) {\n\
+                color.rgb = color.rgb / (1.0 + color.rgb);\n\
+            } else if (tonemap == 2) {\n\
+                color.rgb = aces(color.rgb);\n\
+            } else {\n\
+                color.rgb = min(color.rgb, 1.0);\n\
+            }\n\
+            if (encoding != 2) {\n\
+                color.rgb = linear_to_srgb(color.rgb);\n\
+            }\n\
+            color.rgb = (color.rgb - 0.5) * contrast + 0.5 + brightness;\n\
+            color.rgb = pow(max(color.rgb, 0.0), vec3(1.0 / gamma));\n\
+            if (display_mode == 1) {\n\
+                color = vec4(vec3(color.r), 1.0);\n\
+            } else if (display_mode == 2) {\n\
+                color = vec4(vec3(color.g), 1.0);\n\
+            } else if (display_mode == 3) {\n\
+                color = vec4(vec3(color.b), 1.0);\n\
+            } else if (display_mode == 4) {\n\
+                color = vec4(vec3(color.a), 1.0);\n\
+            } else if (display_mode == 5) {\n\
+                float luma = dot(color.rgb, vec3(0.2126, 0.7152, 0.0722));\n\
+                color = vec4(vec3(luma), 1.0);\n\
+            } else if (display_mode == 6) {\n\
+                color.a = 1.0;\n\
+            }\n\
+            // compositing is in linear light, like the framebuffer's own\n\
+            // blending, and the checker colours are sRGB like the image\n\
+            vec3 linear = srgb_to_linear(clamp(color.rgb, 0.0, 1.0));\n\
+            if (checkerboard) {\n\
+                vec2 cell = floor(gl_FragCoord.xy / checker_size);\n\
+                bool odd = mod(cell.x + cell.y, 2.0) >= 1.0;\n\
+                vec3 checker = srgb_to_linear(odd ? checker_color_b : checker_color_a);\n\
+                linear = mix(checker, linear, color.a);\n\
+                color.a = 1.0;\n\
+            }\n\
+            color.rgb = srgb_framebuffer ? linear : linear_to_srgb(linear);\n\
+            fcolor = color;\n\
         }\n\
         \0";
 
@@ -0,0 +1,86 @@
+use std::path::Path;
+use std::time::Duration;
+
+use jxl_oxide::image::BitDepth;
+use jxl_oxide::{EnumColourEncoding, InitializeResult, JxlImage, RenderingIntent};
+
+use crate::texture::{DecodedFrame, DecodedFrames, FloatImage, FrameSamples};
+
+/// The size a JPEG XL file is shown at, from the start of its data.
+pub fn size(data: &[u8]) -> Result<[u32; 2], Box<dyn std::error::Error>> {
+    let mut image = JxlImage::builder().build_uninit();
+    image.feed_bytes(data).map_err(|e| e.to_string())?;
+    match image.try_init().map_err(|e| e.to_string())? {
+        InitializeResult::Initialized(image) => Ok([image.width(), image.height()]),
+        InitializeResult::NeedMoreData(_) => Err("JPEG XL header is cut short".into()),
+    }
+}
+
+/// Decodes every frame of a JPEG XL file. Float images come out as linear
+/// light, integer ones as sRGB at 8 or 16 bits, whichever holds them.
+pub fn read(path: &Path) -> Result<DecodedFrames, Box<dyn std::error::Error>> {
+    let data = crate::archive::read(path)?;
+    let mut image = JxlImage::builder().read(data.as_slice()).map_err(|e| e.to_string())?;
+
+    let metadata = &image.image_header().metadata;
+    let (float, bits) = match metadata.bit_depth {
+        BitDepth::FloatSample { bits_per_sample, .. } => (true, bits_per_sample),
+        BitDepth::IntegerSample { bits_per_sample } => (false, bits_per_sample),
+    };
+    let tick = metadata.animation.as_ref()
+        .filter(|animation| animation.tps_numerator != 0)
+        .map(|animation| animation.tps_denominator as f64 / animation.tps_numerator as f64)
+        .unwrap_or(0.0);
+
+    let grayscale = image.pixel_format().is_grayscale();
+    let linear = float && !grayscale;
+    image.request_color_encoding(match (grayscale, linear) {
+        (true, _) => EnumColourEncoding::gray_srgb(RenderingIntent::Relative),
+        (false, true) => EnumColourEncoding::srgb_linear(RenderingIntent::Relative),
+        (false, false) => EnumColourEncoding::srgb(RenderingIntent::Relative),
+    });
+
+    if image.num_loaded_keyframes() == 0 {
+        return Err("JPEG XL file has no frames".into());
+    }
+    let mut frames = Vec::with_capacity(image.num_loaded_keyframes());
+    for keyframe in 0..image.num_loaded_keyframes() {
+        let render = image.render_frame(keyframe).map_err(|e| e.to_string())?;
+
+        let mut stream = render.stream();
+        let (width, height, channels) = (stream.width(), stream.height(), stream.channels() as usize);
+        // only a broken file has frames with nothing in them
+        if channels == 0 {
+            return Err("JPEG XL frame has no channels".into());
+        }
+        let mut buffer = vec![0.0f32; width as usize * height as usize * channels];
+        stream.write_to_buffer(&mut buffer);
+
+        let rgba = buffer.chunks_exact(channels).flat_map(|c| match c.len() {
+            1 => [c[0], c[0], c[0], 1.0],
+            2 => [c[0], c[0], c[0], c[1]],
+            3 => [c[0], c[1], c[2], 1.0],
+            _ => [c[0], c[1], c[2], c[3]],
+        });
+
+        let samples = if float {
+            let samples = FloatImage::from_raw(width, height, rgba.collect())
+                .ok_or("JPEG XL frame is the wrong size")?;
+            FrameSamples::F32 { samples, linear, full_float: bits > 16 }
+        } else if bits > 8 {
+            let samples = rgba.map(|c| (c.clamp(0.0, 1.0) * 65535.0).round() as u16).collect();
+            FrameSamples::U16(image::ImageBuffer::from_raw(width, height, samples)
+                .ok_or("JPEG XL frame is the wrong size")?)
+        } else {
+            let samples = rgba.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8).collect();
+            FrameSamples::U8(image::ImageBuffer::from_raw(width, height, samples)
+                .ok_or("JPEG XL frame is the wrong size")?)
+        };
+
+        let duration = Duration::from_secs_f64(render.duration() as f64 * tick);
+        frames.push(DecodedFrame { samples, duration });
+    }
+
+    // colours were already converted to sRGB while rendering
+    Ok(DecodedFrames { frames, icc: None })
+}
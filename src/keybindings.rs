@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+
+use glutin::event::{ModifiersState, VirtualKeyCode};
+
+macro_rules! actions {
+    ($($variant:ident => $name:literal,)*) => {
+        /// Everything a key can be bound to. The names are what the `[keys]`
+        /// table of the config file uses.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize)]
+        #[serde(try_from = "String")]
+        pub enum Action {
+            $($variant,)*
+        }
+
+        impl Action {
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Action::$variant => $name,)*
+                }
+            }
+
+            pub fn from_name(name: &str) -> Option<Action> {
+                match name {
+                    $($name => Some(Action::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+actions! {
+    Unbound => "none",
+    Quit => "quit",
+    Next => "next",
+    Previous => "previous",
+    MoveRight => "move_right",
+    MoveLeft => "move_left",
+    Drop => "drop",
+    ToggleFullscreen => "toggle_fullscreen",
+    ToggleBorderless => "toggle_borderless",
+    ToggleHud => "toggle_hud",
+    CycleBackground => "cycle_background",
+    CycleChannel => "cycle_channel",
+    ToggleHistogram => "toggle_histogram",
+    PinReference => "pin_reference",
+    ToggleBaseline => "toggle_baseline",
+    Approve => "approve",
+    Reject => "reject",
+    NeedsFollowUp => "needs_follow_up",
+    EditNote => "edit_note",
+    ToggleGallery => "toggle_gallery",
+    OpenSelected => "open_selected",
+    ExportMontage => "export_montage",
+    CycleTonemap => "cycle_tonemap",
+    CycleLayer => "cycle_layer",
+    NextPage => "next_page",
+    PreviousPage => "previous_page",
+    ToggleColorManagement => "toggle_color_management",
+    ToggleData => "toggle_data",
+    CycleCompare => "cycle_compare",
+    ToggleHeatmap => "toggle_heatmap",
+    DiffGainUp => "diff_gain_up",
+    DiffGainDown => "diff_gain_down",
+    ToggleCompareAlign => "toggle_compare_align",
+    CycleAdjustment => "cycle_adjustment",
+    AdjustUp => "adjust_up",
+    AdjustDown => "adjust_down",
+    ResetAdjustments => "reset_adjustments",
+    ToggleAdjustmentLock => "toggle_adjustment_lock",
+    ZoomIn => "zoom_in",
+    ZoomOut => "zoom_out",
+    ZoomFit => "zoom_fit",
+    ZoomActual => "zoom_actual",
+    ToggleSpreads => "toggle_spreads",
+    ToggleRightToLeft => "toggle_right_to_left",
+    ToggleStrip => "toggle_strip",
+    GridUp => "grid_up",
+    GridDown => "grid_down",
+    ScrollUp => "scroll_up",
+    ScrollDown => "scroll_down",
+    ScrollPageUp => "scroll_page_up",
+    ScrollPageDown => "scroll_page_down",
+}
+
+impl TryFrom<String> for Action {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Action, String> {
+        Action::from_name(&name).ok_or_else(|| format!("unknown action {:?}", name))
+    }
+}
+
+// the first name listed for a key is the one used when printing it
+const KEY_NAMES: &[(&str, VirtualKeyCode)] = {
+    use VirtualKeyCode::*;
+    &[
+        ("A", A), ("B", B), ("C", C), ("D", D), ("E", E), ("F", F), ("G", G),
+        ("H", H), ("I", I), ("J", J), ("K", K), ("L", L), ("M", M), ("N", N),
+        ("O", O), ("P", P), ("Q", Q), ("R", R), ("S", S), ("T", T), ("U", U),
+        ("V", V), ("W", W), ("X", X), ("Y", Y), ("Z", Z),
+        ("1", Key1), ("2", Key2), ("3", Key3), ("4", Key4), ("5", Key5),
+        ("6", Key6), ("7", Key7), ("8", Key8), ("9", Key9), ("0", Key0),
+        ("F1", F1), ("F2", F2), ("F3", F3), ("F4", F4), ("F5", F5), ("F6", F6),
+        ("F7", F7), ("F8", F8), ("F9", F9), ("F10", F10), ("F11", F11), ("F12", F12),
+        ("Left", Left), ("Right", Right), ("Up", Up), ("Down", Down),
+        ("Escape", Escape), ("Esc", Escape),
+        ("Enter", Return), ("Return", Return),
+        ("Space", Space), ("Tab", Tab),
+        ("Backspace", Back), ("Delete", Delete), ("Del", Delete), ("Insert", Insert),
+        ("Home", Home), ("End", End),
+        ("PageUp", PageUp), ("PgUp", PageUp), ("PageDown", PageDown), ("PgDn", PageDown),
+        ("+", Plus), ("Plus", Plus), ("-", Minus), ("Minus", Minus),
+        ("=", Equals), ("Equals", Equals),
+        (",", Comma), ("Comma", Comma), (".", Period), ("Period", Period),
+        ("/", Slash), ("Slash", Slash), ("\\", Backslash), ("Backslash", Backslash),
+        (";", Semicolon), ("'", Apostrophe), ("`", Grave),
+        ("[", LBracket), ("]", RBracket),
+        ("NumpadAdd", NumpadAdd), ("NumpadSubtract", NumpadSubtract),
+        ("NumpadMultiply", NumpadMultiply), ("NumpadDivide", NumpadDivide),
+        ("NumpadEnter", NumpadEnter),
+        ("Numpad0", Numpad0), ("Numpad1", Numpad1), ("Numpad2", Numpad2),
+        ("Numpad3", Numpad3), ("Numpad4", Numpad4), ("Numpad5", Numpad5),
+        ("Numpad6", Numpad6), ("Numpad7", Numpad7), ("Numpad8", Numpad8),
+        ("Numpad9", Numpad9),
+    ]
+};
+
+/// A key along with the modifiers that have to be held for it, written
+/// like `Ctrl+Shift+Left`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct KeyChord {
+    pub key: VirtualKeyCode,
+    pub modifiers: ModifiersState,
+}
+
+impl KeyChord {
+    pub fn new(key: VirtualKeyCode, modifiers: ModifiersState) -> KeyChord {
+        // only the modifiers we can name in a binding count
+        let known = ModifiersState::CTRL | ModifiersState::SHIFT
+            | ModifiersState::ALT | ModifiersState::LOGO;
+        KeyChord { key, modifiers: modifiers & known }
+    }
+
+    fn plain(key: VirtualKeyCode) -> KeyChord {
+        KeyChord::new(key, ModifiersState::empty())
+    }
+
+    fn shift(key: VirtualKeyCode) -> KeyChord {
+        KeyChord::new(key, ModifiersState::SHIFT)
+    }
+
+    fn ctrl(key: VirtualKeyCode) -> KeyChord {
+        KeyChord::new(key, ModifiersState::CTRL)
+    }
+}
+
+impl std::str::FromStr for KeyChord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<KeyChord, String> {
+        let s = s.trim();
+        let (modifier_names, key_name) = if s == "+" {
+            ("", "+")
+        } else if let Some(rest) = s.strip_suffix("++") {
+            (rest, "+")
+        } else {
+            s.rsplit_once('+').unwrap_or(("", s))
+        };
+
+        let key = KEY_NAMES.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key_name.trim()))
+            .map(|&(_, key)| key)
+            .ok_or_else(|| format!("unknown key {:?} in {:?}", key_name, s))?;
+
+        let mut modifiers = ModifiersState::empty();
+        for name in modifier_names.split('+').map(str::trim).filter(|n| !n.is_empty()) {
+            modifiers |= match name.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => ModifiersState::CTRL,
+                "shift" => ModifiersState::SHIFT,
+                "alt" => ModifiersState::ALT,
+                "logo" | "super" | "cmd" | "win" => ModifiersState::LOGO,
+                _ => return Err(format!("unknown modifier {:?} in {:?}", name, s)),
+            };
+        }
+
+        Ok(KeyChord::new(key, modifiers))
+    }
+}
+
+impl TryFrom<String> for KeyChord {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<KeyChord, String> {
+        s.parse()
+    }
+}
+
+impl std::fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.modifiers.ctrl() { write!(f, "Ctrl+")?; }
+        if self.modifiers.alt() { write!(f, "Alt+")?; }
+        if self.modifiers.shift() { write!(f, "Shift+")?; }
+        if self.modifiers.logo() { write!(f, "Logo+")?; }
+
+        match KEY_NAMES.iter().find(|&&(_, key)| key == self.key) {
+            Some((name, _)) => write!(f, "{}", name),
+            None => write!(f, "{:?}", self.key),
+        }
+    }
+}
+
+pub type Bindings = HashMap<KeyChord, Action>;
+
+/// Bindings that come before the others while the gallery is open.
+pub fn default_gallery_bindings() -> Bindings {
+    use VirtualKeyCode::*;
+
+    let mut bindings = Bindings::new();
+    bindings.insert(KeyChord::plain(Up), Action::GridUp);
+    bindings.insert(KeyChord::plain(Down), Action::GridDown);
+    bindings
+}
+
+/// Bindings that come before the others while reading in the strip.
+pub fn default_strip_bindings() -> Bindings {
+    use VirtualKeyCode::*;
+
+    let mut bindings = Bindings::new();
+    bindings.insert(KeyChord::plain(Up), Action::ScrollUp);
+    bindings.insert(KeyChord::plain(Down), Action::ScrollDown);
+    bindings.insert(KeyChord::plain(PageUp), Action::ScrollPageUp);
+    bindings.insert(KeyChord::plain(PageDown), Action::ScrollPageDown);
+    bindings
+}
+
+pub fn default_bindings() -> Bindings {
+    use VirtualKeyCode::*;
+
+    let mut bindings = Bindings::new();
+    bindings.insert(KeyChord::plain(Escape), Action::Quit);
+    bindings.insert(KeyChord::plain(Right), Action::Next);
+    bindings.insert(KeyChord::plain(Left), Action::Previous);
+    bindings.insert(KeyChord::shift(Right), Action::MoveRight);
+    bindings.insert(KeyChord::shift(Left), Action::MoveLeft);
+    bindings.insert(KeyChord::plain(X), Action::Drop);
+    bindings.insert(KeyChord::plain(F), Action::ToggleFullscreen);
+    bindings.insert(KeyChord::plain(F11), Action::ToggleFullscreen);
+    bindings.insert(KeyChord::plain(B), Action::ToggleBorderless);
+    bindings.insert(KeyChord::plain(H), Action::ToggleHud);
+    bindings.insert(KeyChord::plain(G), Action::CycleBackground);
+    bindings.insert(KeyChord::plain(C), Action::CycleChannel);
+    bindings.insert(KeyChord::plain(I), Action::ToggleHistogram);
+    bindings.insert(KeyChord::plain(P), Action::PinReference);
+    bindings.insert(KeyChord::plain(T), Action::ToggleBaseline);
+    bindings.insert(KeyChord::plain(Y), Action::Approve);
+    bindings.insert(KeyChord::plain(R), Action::Reject);
+    bindings.insert(KeyChord::plain(U), Action::NeedsFollowUp);
+    bindings.insert(KeyChord::plain(N), Action::EditNote);
+    bindings.insert(KeyChord::plain(Tab), Action::ToggleGallery);
+    bindings.insert(KeyChord::plain(Return), Action::OpenSelected);
+    bindings.insert(KeyChord::plain(M), Action::ExportMontage);
+    bindings.insert(KeyChord::plain(O), Action::CycleTonemap);
+    bindings.insert(KeyChord::plain(E), Action::CycleLayer);
+    bindings.insert(KeyChord::plain(PageDown), Action::NextPage);
+    bindings.insert(KeyChord::plain(PageUp), Action::PreviousPage);
+    bindings.insert(KeyChord::shift(C), Action::ToggleColorManagement);
+    bindings.insert(KeyChord::shift(D), Action::ToggleData);
+    bindings.insert(KeyChord::plain(V), Action::CycleCompare);
+    bindings.insert(KeyChord::plain(D), Action::ToggleHeatmap);
+    bindings.insert(KeyChord::plain(RBracket), Action::DiffGainUp);
+    bindings.insert(KeyChord::plain(LBracket), Action::DiffGainDown);
+    bindings.insert(KeyChord::shift(V), Action::ToggleCompareAlign);
+    bindings.insert(KeyChord::plain(A), Action::CycleAdjustment);
+    bindings.insert(KeyChord::plain(Up), Action::AdjustUp);
+    bindings.insert(KeyChord::plain(Down), Action::AdjustDown);
+    bindings.insert(KeyChord::plain(Back), Action::ResetAdjustments);
+    bindings.insert(KeyChord::plain(L), Action::ToggleAdjustmentLock);
+    bindings.insert(KeyChord::plain(Equals), Action::ZoomIn);
+    bindings.insert(KeyChord::plain(Plus), Action::ZoomIn);
+    bindings.insert(KeyChord::plain(NumpadAdd), Action::ZoomIn);
+    bindings.insert(KeyChord::plain(Minus), Action::ZoomOut);
+    bindings.insert(KeyChord::plain(NumpadSubtract), Action::ZoomOut);
+    bindings.insert(KeyChord::plain(Key0), Action::ZoomFit);
+    bindings.insert(KeyChord::plain(Key1), Action::ZoomActual);
+    bindings.insert(KeyChord::plain(S), Action::ToggleSpreads);
+    bindings.insert(KeyChord::shift(S), Action::ToggleRightToLeft);
+    bindings.insert(KeyChord::plain(W), Action::ToggleStrip);
+    bindings.insert(KeyChord::ctrl(Q), Action::Quit);
+    bindings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(s: &str) -> Result<KeyChord, String> {
+        s.parse()
+    }
+
+    #[test]
+    fn parses_chords() {
+        use VirtualKeyCode::*;
+
+        assert_eq!(chord("X"), Ok(KeyChord::plain(X)));
+        assert_eq!(chord("+"), Ok(KeyChord::plain(Plus)));
+        assert_eq!(chord("Ctrl++"), Ok(KeyChord::ctrl(Plus)));
+        assert_eq!(chord("Shift+-"), Ok(KeyChord::shift(Minus)));
+        assert_eq!(
+            chord("ctrl+shift+x"),
+            Ok(KeyChord::new(X, ModifiersState::CTRL | ModifiersState::SHIFT)),
+        );
+        assert_eq!(chord(" Alt + PgUp "), Ok(KeyChord::new(PageUp, ModifiersState::ALT)));
+        assert_eq!(chord("Super+Left"), Ok(KeyChord::new(Left, ModifiersState::LOGO)));
+    }
+
+    #[test]
+    fn names_are_case_insensitive() {
+        for s in ["CTRL+SHIFT+PAGEDOWN", "ctrl+shift+pagedown", "Control+Shift+PageDown"] {
+            assert_eq!(chord(s), chord("Ctrl+Shift+PageDown"), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn reports_unknown_names() {
+        let err = chord("Ctrl+Nope").unwrap_err();
+        assert!(err.contains("unknown key \"Nope\""), "{}", err);
+        let err = chord("Hyper+X").unwrap_err();
+        assert!(err.contains("unknown modifier \"Hyper\""), "{}", err);
+        assert!(chord("").is_err());
+        assert!(chord("Ctrl+").is_err());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for s in ["X", "+", "Ctrl++", "Ctrl+Alt+Shift+Logo+F5", "Shift+Left", "NumpadEnter"] {
+            let parsed = chord(s).unwrap();
+            assert_eq!(parsed.to_string(), s);
+            assert_eq!(chord(&parsed.to_string()), Ok(parsed));
+        }
+        // every default binding can be written back into a config file
+        for bindings in [default_bindings(), default_gallery_bindings(), default_strip_bindings()] {
+            for chord in bindings.keys() {
+                assert_eq!(chord.to_string().parse(), Ok(*chord));
+            }
+        }
+    }
+}
@@ -3,66 +3,220 @@
 use clap::Parser;
 
 mod image_renderer;
-use image_renderer::{Renderer, ImageRenderer};
+use image_renderer::{Renderer, ImageRenderer, DisplayMode, ReferenceOp, ReferenceView};
 
 mod texture;
-use texture::Texture;
+mod exr_image;
+mod svg_image;
+mod pages;
+mod archive;
+#[cfg(any(feature = "avif", feature = "heic"))]
+mod heif_image;
+#[cfg(feature = "jxl")]
+mod jxl_image;
+use texture::{LoadSettings, SampleFormat, Texture};
+
+mod color_management;
+
+mod text_renderer;
+use text_renderer::TextRenderer;
+
+mod hud;
+use hud::{Hud, HudCorner};
 
 // mod shader;
 
+mod window_geometry;
+use window_geometry::Geometry;
+
+mod keybindings;
+use keybindings::{Action, KeyChord};
+
+mod config;
+use config::{Color, Config, SortOrder, Zoom};
+
+mod adjustments;
+use adjustments::{Adjustment, Adjustments, Tonemap};
+
+mod background;
+use background::{Background, Checkerboard};
+
+mod stats;
+use stats::{ImageStats, StatsWorker};
+
+mod compare;
+use compare::{CompareMode, DiffSettings, Drag, Flicker};
+
+mod metrics;
+use metrics::{DiffMetrics, Tolerances};
+
+mod image_list;
+use image_list::{EntryPaths, FileSignature, ImageEntry, Side, TextureFile};
+
+mod review;
+use review::{ReviewSession, Verdict};
+
+mod gallery;
+use gallery::Gallery;
+
+mod thumbnail_cache;
+use thumbnail_cache::ThumbnailCache;
+
+mod montage;
+use montage::MontageOptions;
+
+mod spread;
+use spread::Spreads;
+mod strip;
+use strip::Strip;
+
+
+use glutin::window::Fullscreen;
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
-    let image_paths = {
-        if cli.image_paths.len() != 0 {
-            let mut image_paths = Vec::new();
-            for p in cli.image_paths.iter() {
-                if p.is_dir() {
-                    image_paths.append(&mut all_images_in_directory(p)?);
-                } else {
-                    image_paths.push(p.clone());
-                }
+    if let Some(command) = &cli.command {
+        return run_command(command);
+    }
+
+    let config = match Config::load(cli.config.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        },
+    };
+    let bindings = config.bindings();
+    let gallery_bindings = config.gallery_bindings();
+    let strip_bindings = config.strip_bindings();
+
+    if cli.list_keys {
+        let tables = [
+            (None, &bindings),
+            (Some("in the gallery"), &gallery_bindings),
+            (Some("in the strip"), &strip_bindings),
+        ];
+        for (heading, bindings) in tables {
+            if let Some(heading) = heading {
+                println!("\n{}:", heading);
             }
-            image_paths
-        } else {
-            all_images_in_directory(".")?
+            let mut lines: Vec<_> = bindings.iter()
+                .map(|(chord, action)| format!("{:<20} {}", chord.to_string(), action.name()))
+                .collect();
+            lines.sort();
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+        return Ok(());
+    }
+
+    let image_paths = if let Some(dirs) = &cli.pair {
+        let pairs = image_list::paired_paths(&dirs[0], &dirs[1])?;
+        if pairs.is_empty() {
+            return Err("no images found under either directory".into());
         }
+        pairs
+    } else {
+        let paths = if !cli.image_paths.is_empty() {
+            image_list::expand_paths(&cli.image_paths, config.sort)?
+        } else {
+            image_list::all_images_in_directory(".", config.sort)?
+        };
+        paths.into_iter().map(EntryPaths::single).collect()
+    };
+
+    let review = match ReviewSession::load(cli.review.as_deref()) {
+        Ok(review) => review,
+        Err(e) => {
+            eprintln!("failed to load review: {}", e);
+            std::process::exit(1);
+        },
     };
 
     let el = glutin::event_loop::EventLoop::new();
-    let wb = glutin::window::WindowBuilder::new()
-        .with_title(image_paths[0].to_string_lossy().to_owned());
-    
-    let wc = glutin::ContextBuilder::new().build_windowed(wb, &el).unwrap();
+    let mut wb = glutin::window::WindowBuilder::new()
+        .with_title(image_paths[0].path.to_string_lossy().into_owned())
+        .with_decorations(!cli.borderless);
+
+    let monitor_size = el.primary_monitor().map(|m| [m.size().width, m.size().height]);
+    let geometry = if let Some(geometry) = cli.geometry {
+        Some(geometry)
+    } else if cli.fit_image {
+        match (texture::probe_size(&image_paths[0].path, &LoadSettings::from_config(&config)), monitor_size) {
+            (Ok([w, h]), Some(m)) => {
+                let size = window_geometry::fit_to_monitor([w as u32, h as u32], m);
+                Some(Geometry { size, position: None })
+            },
+            _ => None,
+        }
+    } else {
+        window_geometry::load_saved()
+    };
+
+    if let Some(geometry) = geometry {
+        wb = geometry.apply(wb, monitor_size);
+    }
+
+    if cli.fullscreen {
+        wb = wb.with_fullscreen(Some(Fullscreen::Borderless(None)));
+    }
+
+    let wc = glutin::ContextBuilder::new()
+        .with_srgb(true)
+        .build_windowed(wb, &el).unwrap();
     let wc = unsafe { wc.make_current().unwrap() };
     
     gl::load_with(|p| wc.get_proc_address(p) as *const _);
     
-    let mut app_data = AppData::new(image_paths);
+    let mut app_data = AppData::new(image_paths, &config);
+    // without an sRGB framebuffer the shader encodes its output itself
+    app_data.renderer.set_srgb_framebuffer(wc.get_pixel_format().srgb);
+    app_data.hud = Hud::new(cli.hud || config.hud, cli.hud_corner.unwrap_or(config.hud_corner));
+    app_data.review = review;
+    app_data.report_path = cli.report.clone();
+    if cli.approve_copies {
+        app_data.copy_approved_to = cli.pair.as_ref().map(|dirs| dirs[0].clone());
+    }
+
+    // the geometry to remember if we exit while fullscreen
+    let mut windowed_geometry = geometry;
+    let mut decorations = !cli.borderless;
+    let mut modifiers = glutin::event::ModifiersState::empty();
 
     let frame_duration = std::time::Duration::new(0, 1000000000 / 60);
     let mut next_update_time = std::time::Instant::now() + frame_duration;
 
     el.run(move |event, _, control_flow| {
-        use glutin::event::{Event, WindowEvent, StartCause};
+        use glutin::event::{Event, WindowEvent, StartCause, ElementState, MouseButton};
         use glutin::event_loop::ControlFlow;
 
         *control_flow = ControlFlow::WaitUntil(next_update_time);
 
         match event {
-            Event::LoopDestroyed => return,
-
-            Event::NewEvents(cause) => match cause {
-                StartCause::ResumeTimeReached { .. } => {
-                    if app_data.update(frame_duration.as_secs_f32()) {
-                        wc.window().request_redraw();
+            Event::LoopDestroyed => {
+                let window = wc.window();
+                let geometry = match window.fullscreen() {
+                    None => Some(Geometry::of_window(window)),
+                    Some(_) => windowed_geometry,
+                };
+                if let Some(geometry) = geometry {
+                    if let Err(e) = window_geometry::save(geometry) {
+                        eprintln!("failed to save window geometry: {}", e);
                     }
+                }
+                app_data.write_report();
+            },
 
-                    next_update_time = next_update_time + frame_duration;
-                    *control_flow = ControlFlow::WaitUntil(next_update_time);
-                },
+            Event::NewEvents(StartCause::ResumeTimeReached { .. }) => {
+                if app_data.update(frame_duration.as_secs_f32()) {
+                    wc.window().set_title(&app_data.new_window_title());
+                    wc.window().request_redraw();
+                }
 
-                _ => (),
+                next_update_time += frame_duration;
+                *control_flow = ControlFlow::WaitUntil(next_update_time);
             },
 
             Event::WindowEvent { event, .. } => match event {
@@ -72,44 +226,107 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     app_data.resize_window([physical_size.width as _, physical_size.height as _]);
                 },
 
+                WindowEvent::ModifiersChanged(state) => modifiers = state,
+
+                WindowEvent::ReceivedCharacter(c) if app_data.editing_note() => {
+                    app_data.note_input(c);
+                    wc.window().set_title(&app_data.new_window_title());
+                    wc.window().request_redraw();
+                },
+
+                // typing a note, where keys go in as characters instead
+                WindowEvent::KeyboardInput { .. } if app_data.editing_note() => (),
+
                 WindowEvent::KeyboardInput { input, .. } => {
-                    use glutin::event::VirtualKeyCode::{Escape, Left, Right, X};
                     use glutin::event::ElementState::Pressed;
-                    match (input.virtual_keycode, input.state) {
-                        (Some(Escape), Pressed) => *control_flow = ControlFlow::Exit,
-                        (Some(Left), Pressed) => {
-                            #[allow(deprecated)]
-                            if input.modifiers.shift() {
-                                app_data.shift_left();
-                                wc.window().set_title(&app_data.new_window_title());
+                    let key = match (input.virtual_keycode, input.state) {
+                        (Some(key), Pressed) => key,
+                        _ => return,
+                    };
+
+                    // the gallery's and the strip's own bindings come first
+                    // while they're showing
+                    let chord = KeyChord::new(key, modifiers);
+                    let mode_bindings = if app_data.gallery.visible {
+                        Some(&gallery_bindings)
+                    } else {
+                        app_data.strip.enabled.then_some(&strip_bindings)
+                    };
+                    let action = match mode_bindings.and_then(|b| b.get(&chord)).or_else(|| bindings.get(&chord)) {
+                        Some(&action) => action,
+                        None => return,
+                    };
+
+                    match action {
+                        Action::Quit => *control_flow = ControlFlow::Exit,
+                        Action::ToggleFullscreen => {
+                            let window = wc.window();
+                            if window.fullscreen().is_some() {
+                                window.set_fullscreen(None);
                             } else {
-                                app_data.cycle_left();
-                                wc.window().set_title(&app_data.new_window_title());
-                                wc.window().request_redraw();
+                                windowed_geometry = Some(Geometry::of_window(window));
+                                window.set_fullscreen(Some(Fullscreen::Borderless(None)));
                             }
                         },
-                        (Some(Right), Pressed) => {
-                            #[allow(deprecated)]
-                            if input.modifiers.shift() {
-                                app_data.shift_right();
-                                wc.window().set_title(&app_data.new_window_title());
-                            } else {
-                                app_data.cycle_right();
-                                wc.window().set_title(&app_data.new_window_title());
+                        Action::ToggleBorderless => {
+                            decorations = !decorations;
+                            wc.window().set_decorations(decorations);
+                        },
+                        action => {
+                            if app_data.perform(action) {
                                 wc.window().request_redraw();
                             }
-                        },
-                        (Some(X), Pressed) => {
-                            app_data.drop_current();
                             wc.window().set_title(&app_data.new_window_title());
-                            wc.window().request_redraw();
-                        }
-                        _ => (),
+                        },
+                    }
+                },
+
+                WindowEvent::MouseWheel { delta, .. } if app_data.gallery.visible => {
+                    use glutin::event::MouseScrollDelta;
+                    let pixels = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => -y * 60.0,
+                        MouseScrollDelta::PixelDelta(p) => -p.y as f32,
+                    };
+                    app_data.scroll_gallery(pixels);
+                    wc.window().request_redraw();
+                },
+
+                WindowEvent::MouseWheel { delta, .. } if modifiers.ctrl() => {
+                    use glutin::event::MouseScrollDelta;
+                    let steps = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => y,
+                        MouseScrollDelta::PixelDelta(p) => p.y as f32 / 50.0,
+                    };
+                    app_data.step_adjustment(steps);
+                    wc.window().set_title(&app_data.new_window_title());
+                    wc.window().request_redraw();
+                },
+
+                WindowEvent::MouseWheel { delta, .. } if app_data.strip.enabled => {
+                    use glutin::event::MouseScrollDelta;
+                    let pixels = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => -y * 60.0,
+                        MouseScrollDelta::PixelDelta(p) => -p.y as f32,
+                    };
+                    // update scrolls there, and redraws as it goes
+                    app_data.strip.scroll_by(pixels);
+                },
+
+                WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+                    match state {
+                        ElementState::Pressed => app_data.mouse_pressed(),
+                        ElementState::Released => app_data.mouse_released(),
+                    }
+                    if app_data.gallery.visible {
+                        wc.window().set_title(&app_data.new_window_title());
+                        wc.window().request_redraw();
                     }
                 },
 
                 WindowEvent::CursorMoved { position, .. } => {
-                    app_data.cursor_position = [position.x as i32, position.y as i32];
+                    if app_data.cursor_moved([position.x as i32, position.y as i32]) {
+                        wc.window().request_redraw();
+                    }
                     wc.window().set_title(&app_data.new_window_title());
                 }
 
@@ -126,145 +343,1097 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
 }
 
-fn all_images_in_directory<P: AsRef<std::path::Path>>(dir: P)
-    -> std::io::Result<Vec<std::path::PathBuf>>
-{
-    let mut paths = Vec::new();
-
-    for entry in std::fs::read_dir(dir)? {
-        let entry = entry?;
-        if let Some(ext) = entry.path().extension() {
-            if let Some(ext_str) = ext.to_str() {
-                match ext_str {
-                    "png" | "jpg" | "bmp" | "gif" | "jpeg"
-                        => paths.push(entry.path().clone()),
-                    _ => (),
+fn run_command(command: &Command) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        Command::Stats { image_paths } => {
+            for p in image_paths.iter() {
+                let img = texture::decode_rgba8(p, None).map_err(|e| format!("{}: {}", p.display(), e))?;
+                println!("{} {}x{}", p.display(), img.width(), img.height());
+                for line in ImageStats::compute(&img).lines() {
+                    println!("  {}", line);
                 }
             }
-        }
+        },
+
+        Command::Diff { a, b, threshold, min_psnr, min_ssim, max_error, max_changed } => {
+            let tolerances = Tolerances {
+                min_psnr: *min_psnr,
+                min_ssim: *min_ssim,
+                max_error: *max_error,
+                max_changed: *max_changed,
+            };
+            // images that can't be compared at all still get a report, so
+            // whatever reads it sees why
+            let (metrics, failures) = match diff_files(a, b, *threshold) {
+                Ok(metrics) => {
+                    let failures = tolerances.failures(&metrics);
+                    (Some(metrics), failures)
+                },
+                Err(e) => (None, vec![e]),
+            };
+
+            let report = serde_json::json!({
+                "a": a,
+                "b": b,
+                "metrics": metrics,
+                "failures": failures,
+                "passed": failures.is_empty(),
+            });
+            println!("{}", serde_json::to_string_pretty(&report)?);
+
+            if !failures.is_empty() {
+                std::process::exit(1);
+            }
+        },
+
+        Command::Montage { image_paths, output, columns, tile_size, padding, background, no_captions } => {
+            let paths = if image_paths.is_empty() {
+                image_list::all_images_in_directory(".", SortOrder::Name)?
+            } else {
+                image_list::expand_paths(image_paths, SortOrder::Name)?
+            };
+            if paths.is_empty() {
+                return Err("no images to put in the montage".into());
+            }
+
+            let options = MontageOptions {
+                columns: *columns,
+                tile_size: *tile_size,
+                padding: *padding,
+                background: *background,
+                captions: !no_captions,
+            };
+            montage::write(&paths, output, options)?;
+        },
+
+        Command::PruneThumbnails { max_age, dry_run } => {
+            let max_age = max_age.map(|days| std::time::Duration::from_secs(days * 24 * 60 * 60));
+            let summary = thumbnail_cache::prune(max_age, *dry_run)?;
+            let verb = if *dry_run { "would remove" } else { "removed" };
+            println!("{} {} thumbnails ({}), kept {}", verb, summary.removed,
+                hud::format_file_size(summary.bytes_freed), summary.kept);
+        },
     }
 
-    Ok(paths)
+    Ok(())
 }
 
 /// A basic image viewer
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     image_paths: Vec<std::path::PathBuf>,
-}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-struct FileSignature {
-    modified: Option<std::time::SystemTime>,
-    created: Option<std::time::SystemTime>,
-    len: u64,
-}
+    /// Review two directory trees side by side, matching files by their
+    /// path relative to each
+    #[arg(long, num_args = 2, value_names = ["BASELINE", "CANDIDATE"],
+        conflicts_with = "image_paths")]
+    pair: Option<Vec<std::path::PathBuf>>,
 
-impl FileSignature {
-    fn new<P: AsRef<std::path::Path>>(path: P) -> Result<FileSignature, Box<dyn std::error::Error>> {
-        let mdata = std::fs::metadata(path)?;
-        Ok(FileSignature {
-            modified: mdata.modified().ok(),
-            created: mdata.created().ok(),
-            len: mdata.len(),
-        })
-    }
+    /// Record verdicts in this JSON file, resuming the review in it if it
+    /// already exists
+    #[arg(long, value_name = "FILE")]
+    review: Option<std::path::PathBuf>,
+
+    /// Write a summary of the verdicts to this JSON file, and as HTML
+    /// alongside it
+    #[arg(long, value_name = "FILE")]
+    report: Option<std::path::PathBuf>,
+
+    /// When an image in a paired review is approved, copy the candidate
+    /// over the baseline
+    #[arg(long, requires = "pair", conflicts_with = "image_paths")]
+    approve_copies: bool,
+
+    /// Window size and position, as WxH or WxH+X+Y
+    #[arg(long)]
+    geometry: Option<Geometry>,
+
+    /// Size the window to fit the first image, clamped to the monitor
+    #[arg(long, conflicts_with = "geometry")]
+    fit_image: bool,
+
+    /// Open the window without decorations
+    #[arg(long)]
+    borderless: bool,
+
+    /// Start in fullscreen
+    #[arg(long)]
+    fullscreen: bool,
+
+    /// Start with the info overlay shown
+    #[arg(long)]
+    hud: bool,
+
+    /// Which corner of the window the info overlay is drawn in
+    #[arg(long, value_enum)]
+    hud_corner: Option<HudCorner>,
+
+    /// Read settings from this file instead of the default config.toml
+    #[arg(long)]
+    config: Option<std::path::PathBuf>,
+
+    /// Print the active key bindings and exit
+    #[arg(long)]
+    list_keys: bool,
 }
 
-#[derive(Debug)]
-struct TextureFile {
-    texture: Texture,
-    path: std::path::PathBuf,
-    sig: FileSignature,
+#[derive(Debug, clap::Subcommand)]
+enum Command {
+    /// Print per-channel statistics for images without opening a window
+    Stats {
+        #[arg(required = true)]
+        image_paths: Vec<std::path::PathBuf>,
+    },
+
+    /// Print PSNR, SSIM and changed pixel counts for two images as JSON,
+    /// exiting with status 1 if any given tolerance is exceeded
+    Diff {
+        a: std::path::PathBuf,
+        b: std::path::PathBuf,
+
+        /// Channel difference above which a pixel counts as changed
+        #[arg(long, default_value_t = 0)]
+        threshold: u8,
+
+        /// Fail if PSNR is below this many dB
+        #[arg(long)]
+        min_psnr: Option<f64>,
+
+        /// Fail if SSIM is below this
+        #[arg(long)]
+        min_ssim: Option<f64>,
+
+        /// Fail if any channel of any pixel differs by more than this
+        #[arg(long)]
+        max_error: Option<u8>,
+
+        /// Fail if more than this many pixels changed
+        #[arg(long)]
+        max_changed: Option<u64>,
+    },
+
+    /// Render images into a grid contact sheet, saved as PNG or JPEG going
+    /// by the output file's extension
+    Montage {
+        /// Images or directories of images, defaulting to the current
+        /// directory
+        image_paths: Vec<std::path::PathBuf>,
+
+        #[arg(short, long)]
+        output: std::path::PathBuf,
+
+        /// Defaults to enough for a roughly square sheet
+        #[arg(long)]
+        columns: Option<usize>,
+
+        /// Each image is scaled down to fit a square this many pixels across
+        #[arg(long, default_value_t = 256, value_parser = clap::value_parser!(u32).range(16..))]
+        tile_size: u32,
+
+        #[arg(long, default_value_t = 8)]
+        padding: u32,
+
+        /// As #rrggbb
+        #[arg(long, default_value = "#ffffff")]
+        background: Color,
+
+        /// Leave out the file names under each image
+        #[arg(long)]
+        no_captions: bool,
+    },
+
+    /// Remove thumbnails from ~/.cache/thumbnails whose files have been
+    /// deleted or changed since
+    PruneThumbnails {
+        /// Also remove thumbnails older than this many days
+        #[arg(long, value_name = "DAYS")]
+        max_age: Option<u64>,
+
+        /// Only report what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
+/// The reference and current image files that a set of diff metrics
+/// was computed from.
+type MetricsKey = [(std::path::PathBuf, FileSignature); 2];
+
+/// How many images either side of the one being shown stay loaded, so
+/// stepping back and forth doesn't read them again.
+const KEEP_LOADED: usize = 2;
+
 #[derive(Debug)]
 struct AppData {
-    image_paths: Vec<TextureFile>,
+    image_paths: Vec<ImageEntry>,
     current_image_index: usize,
+    // in a paired review, show the baseline rather than the candidate
+    show_baseline: bool,
     window_size: [i32;2],
     cursor_position: [i32;2],
     renderer: StableAspectRatioImageRenderer,
+    text_renderer: TextRenderer,
+    hud: Hud,
+    background: Background,
+    display_mode: DisplayMode,
+    adjustments: Adjustments,
+    active_adjustment: Adjustment,
+    adjustments_locked: bool,
+    tonemap: Tonemap,
+    load_settings: LoadSettings,
+    default_zoom: Zoom,
+    stats_worker: StatsWorker,
+    show_histogram: bool,
+    reference: Option<TextureFile>,
+    compare_mode: CompareMode,
+    // where the split line is, as a fraction of the window width
+    split: f32,
+    drag: Option<Drag>,
+    diff: DiffSettings,
+    flicker: Flicker,
+    diff_threshold: u8,
+    diff_metrics: Option<(MetricsKey, Result<DiffMetrics, String>)>,
+    metrics_job: Option<(MetricsKey, std::sync::mpsc::Receiver<Result<DiffMetrics, String>>)>,
+    review: ReviewSession,
+    report_path: Option<std::path::PathBuf>,
+    // baseline directory approved candidates get copied into
+    copy_approved_to: Option<std::path::PathBuf>,
+    // the note being typed, if any
+    note: Option<String>,
+    gallery: Gallery,
+    spreads: Spreads,
+    strip: Strip,
+    montage_job: Option<std::sync::mpsc::Receiver<Result<std::path::PathBuf, String>>>,
     
     seconds_elapsed: f32,
 }
 
 impl AppData {
-    fn new(image_paths: Vec<std::path::PathBuf>) -> AppData {
+    fn new(image_paths: Vec<EntryPaths>, config: &Config) -> AppData {
         let renderer = StableAspectRatioImageRenderer::new();
         // renderer.set_texture_data(&image_paths[0]).unwrap();
 
-        let image_paths = image_paths.iter().map(|p| {
-                let sig = FileSignature::new(p).unwrap();
-                let texture = Texture::from_file(p).unwrap();
-                TextureFile { texture, path: p.clone(), sig }
-            }
-        ).collect();
+        let load_settings = LoadSettings::from_config(config);
+        let image_paths = image_paths.iter()
+            .map(|p| p.entry().unwrap())
+            .collect::<Vec<_>>();
 
         let mut app_data = AppData {
             image_paths,
             current_image_index: 0,
+            show_baseline: false,
             window_size: [1,1],
             cursor_position: [0,0],
             renderer,
+            text_renderer: TextRenderer::new(),
+            hud: Hud::new(config.hud, config.hud_corner),
+            background: Background::from_config(config),
+            display_mode: DisplayMode::Normal,
+            adjustments: Adjustments::default(),
+            active_adjustment: Adjustment::Exposure,
+            adjustments_locked: false,
+            tonemap: config.tonemap,
+            load_settings,
+            default_zoom: config.default_zoom,
+            stats_worker: StatsWorker::new(),
+            show_histogram: false,
+            reference: None,
+            compare_mode: CompareMode::Off,
+            split: 0.5,
+            drag: None,
+            diff: DiffSettings {
+                gain: config.diff_gain,
+                heatmap: false,
+                align: config.compare_align,
+            },
+            flicker: Flicker::new(config.flicker_rate),
+            diff_threshold: config.diff_threshold,
+            diff_metrics: None,
+            metrics_job: None,
+            review: ReviewSession::default(),
+            report_path: None,
+            copy_approved_to: None,
+            note: None,
+            gallery: Gallery::new(config.thumbnail_cache.then(ThumbnailCache::new).flatten()),
+            spreads: Spreads::from_config(config),
+            strip: Strip::new(),
+            montage_job: None,
             seconds_elapsed: 0.0,
         };
     
-        if let Err(_) = app_data.reload_texture() {
-            eprintln!("failed to load {:?}", app_data.image_paths[0].path);
+        if app_data.reload_texture().is_err() {
+            eprintln!("failed to load {:?}", app_data.image_paths[0].file.path);
             std::process::exit(-1);
         }
+        app_data.reset_view();
+        app_data.renderer.set_tonemap(app_data.tonemap);
 
         app_data
     }
 
-    fn redraw(&self) {
+    fn redraw(&mut self) {
         unsafe {
-            gl::ClearColor(0.1, 0.1, 0.1, 1.0);
+            let [r, g, b] = self.background.clear_color();
+            gl::ClearColor(r, g, b, 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT);
+        }
+
+        if self.gallery.visible {
+            self.gallery.render(&mut self.text_renderer, &self.image_paths, self.current_image_index);
+        } else {
+            self.renderer.set_checkerboard(self.background.checkerboard());
+            if let Err(e) = self.render_images() {
+                eprintln!("failed to render: {}", e);
+            }
+        }
+
+        if self.hud.visible {
+            let lines = self.hud_lines();
+            self.hud.queue(&mut self.text_renderer, &lines, self.window_size);
+        }
+
+        if self.show_histogram && !self.gallery.visible {
+            if let Some(stats) = self.shown().stats.clone() {
+                // keep out of the HUD's way if it's down in the bottom right
+                let right_side = !(self.hud.visible && self.hud.corner == HudCorner::BottomRight);
+                stats::queue_panel(&mut self.text_renderer, &stats, self.window_size, right_side);
+            }
+        }
+
+        self.text_renderer.flush();
+    }
+
+    fn current(&self) -> &ImageEntry {
+        &self.image_paths[self.current_image_index]
+    }
+
+    /// The file being displayed: in a paired review this can be either
+    /// side of the pair.
+    fn shown(&self) -> &TextureFile {
+        shown_file(self.current(), self.show_baseline)
+    }
+
+    /// The spread the image at `index` is part of, when reading in spreads.
+    /// Comparing shows single images, since that's what's compared, and
+    /// the strip shows them all.
+    fn spread_at(&self, index: usize) -> Option<std::ops::Range<usize>> {
+        if !self.spreads.enabled || self.comparing() || self.strip.enabled {
+            return None;
+        }
+        let sizes: Vec<_> = self.image_paths.iter()
+            .map(|entry| shown_file(entry, self.show_baseline).size(&self.load_settings))
+            .collect();
+        self.spreads.layout(&sizes).into_iter().find(|spread| spread.contains(&index))
+    }
+
+    fn current_spread(&self) -> Option<std::ops::Range<usize>> {
+        self.spread_at(self.current_image_index)
+    }
+
+    /// The size of the spread being shown, as one image with its pages
+    /// scaled to the height of the tallest, if it's more than one page.
+    fn spread_size(&self) -> Option<[i32;2]> {
+        let spread = self.current_spread().filter(|spread| spread.len() > 1)?;
+        let sizes: Vec<_> = spread
+            .map(|i| shown_file(&self.image_paths[i], self.show_baseline).size(&self.load_settings))
+            .collect();
+        let height = sizes.iter().map(|size| size[1]).max()?;
+        let width: f32 = sizes.iter()
+            .map(|size| size[0] as f32 * height as f32 / size[1].max(1) as f32)
+            .sum();
+        Some([width.round() as i32, height])
+    }
+
+    /// Which images the current spread shows, for the title and HUD.
+    fn spread_summary(&self) -> Option<String> {
+        let spread = self.current_spread()?;
+        let pages = if spread.len() > 1 {
+            format!("{}-{}", spread.start + 1, spread.end)
+        } else {
+            (spread.start + 1).to_string()
+        };
+        let direction = if self.spreads.right_to_left { ", right to left" } else { "" };
+        Some(format!("spread {}{}", pages, direction))
+    }
+
+    fn toggle_spreads(&mut self) {
+        self.spreads.enabled = !self.spreads.enabled;
+        // spreads are known by their first image
+        if let Some(spread) = self.current_spread() {
+            self.current_image_index = spread.start;
+        }
+        self.reload_texture().unwrap();
+        self.reset_view();
+    }
+
+    /// Where each image is in the strip, at the window's width.
+    fn strip_layout(&self) -> Vec<(f32, f32)> {
+        let sizes: Vec<_> = self.image_paths.iter()
+            .map(|entry| shown_file(entry, self.show_baseline).size(&self.load_settings))
+            .collect();
+        Strip::layout(&sizes, self.window_size[0] as f32)
+    }
+
+    /// How far through the strip the window is, for the title and HUD.
+    fn strip_summary(&self) -> Option<String> {
+        if !self.strip.enabled {
+            return None;
+        }
+        let progress = self.strip.progress(&self.strip_layout(), self.window_size[1] as f32);
+        Some(format!("strip {:.0}%", progress * 100.0))
+    }
+
+    fn toggle_strip(&mut self) {
+        self.strip.enabled = !self.strip.enabled;
+        if self.strip.enabled {
+            // start reading from the top of the image that was shown
+            let layout = self.strip_layout();
+            self.strip.jump_to(layout[self.current_image_index].0);
+        }
+        self.reload_texture().unwrap();
+        self.reset_view();
+    }
+
+    /// Scrolls the strip along, keeps the images near the window loaded and
+    /// lets the rest go, and makes whichever image is across the middle of
+    /// the window the current one. Returns whether the window needs
+    /// redrawing.
+    fn update_strip(&mut self, seconds_elapsed: f32) -> bool {
+        if !self.strip.enabled || self.gallery.visible {
+            return false;
+        }
+        let layout = self.strip_layout();
+        let height = self.window_size[1] as f32;
+        let moved = self.strip.update(seconds_elapsed, &layout, height);
+
+        // a window's height either way, so scrolling doesn't wait on loading
+        let resident = self.strip.visible(&layout, height, height);
+        let mut loaded = false;
+        for i in 0..self.image_paths.len() {
+            if resident.contains(&i) {
+                loaded |= self.load_entry(i);
+            } else if i != self.current_image_index {
+                self.image_paths[i].files_mut().for_each(TextureFile::unload);
+            }
+        }
+
+        match self.strip.centered(&layout, height) {
+            Some(centered) if centered != self.current_image_index => {
+                self.current_image_index = centered;
+                self.reload_texture().unwrap();
+                true
+            },
+            _ => moved || loaded,
+        }
+    }
+
+    /// Loads the files of the entry at `index` if they aren't already,
+    /// starting on their stats. Returns whether anything was loaded.
+    fn load_entry(&mut self, index: usize) -> bool {
+        let mut loaded = false;
+        for f in self.image_paths[index].files_mut() {
+            if f.ensure_loaded(&self.load_settings) {
+                if f.stats.is_none() {
+                    self.stats_worker.request((f.path.clone(), f.sig), f.texture().pixels.clone());
+                }
+                loaded = true;
+            }
+        }
+        loaded
+    }
+
+    /// Lets go of images more than `KEEP_LOADED` away from the current one,
+    /// or from its spread. The strip keeps track of its own.
+    fn unload_distant(&mut self) {
+        if self.strip.enabled {
+            return;
+        }
+        let index = self.current_image_index;
+        let shown = self.current_spread().unwrap_or(index..index + 1);
+        let kept = shown.start.saturating_sub(KEEP_LOADED)..shown.end + KEEP_LOADED;
+        for (i, entry) in self.image_paths.iter_mut().enumerate() {
+            if !kept.contains(&i) {
+                entry.files_mut().for_each(TextureFile::unload);
+            }
+        }
+    }
+
+    fn shown_mut(&mut self) -> &mut TextureFile {
+        let show_baseline = self.show_baseline;
+        let entry = &mut self.image_paths[self.current_image_index];
+        match &mut entry.baseline {
+            Some(baseline) if show_baseline => baseline,
+            _ => &mut entry.file,
+        }
+    }
+
+    /// Shows the next layer of a multi-layer image, such as an EXR with
+    /// render passes.
+    fn cycle_layer(&mut self) -> bool {
+        let f = self.shown_mut();
+        let texture = f.texture_mut();
+        let count = texture.layers.len();
+        if count < 2 {
+            return false;
+        }
+        if let Err(e) = texture.set_layer((texture.layer + 1) % count) {
+            eprintln!("failed to switch layer: {}", e);
+            return false;
+        }
+        f.stats = None;
+        let (key, pixels) = ((f.path.clone(), f.sig), f.texture().pixels.clone());
+        self.stats_worker.request(key, pixels);
+        self.reload_texture().unwrap();
+        true
+    }
+
+    /// Shows another page of a file holding several images, like a
+    /// multi-page TIFF, staying on the same file.
+    fn step_page(&mut self, step: isize) -> bool {
+        let settings = self.load_settings.clone();
+        let f = self.shown_mut();
+        let last = f.texture().page_count as isize - 1;
+        let page = (f.page as isize + step).clamp(0, last.max(0)) as usize;
+        if page == f.page {
+            return false;
+        }
+        if let Err(e) = f.set_page(page, &settings) {
+            eprintln!("failed to load page {} of {:?}: {}", page + 1, f.path, e);
+            return false;
+        }
+        let (key, pixels) = ((f.path.clone(), f.sig), f.texture().pixels.clone());
+        self.stats_worker.request(key, pixels);
+        self.reload_texture().unwrap();
+        true
+    }
+
+    /// Switches every loaded image between being converted from its
+    /// embedded profile and showing its values unmanaged.
+    fn toggle_color_management(&mut self) -> bool {
+        self.load_settings.color.enabled = !self.load_settings.color.enabled;
+        let enabled = self.load_settings.color.enabled;
+
+        let mut changed = Vec::new();
+        let files = self.image_paths.iter_mut().flat_map(ImageEntry::files_mut)
+            .chain(self.reference.iter_mut());
+        // the rest are loaded with the new setting when they're shown
+        for f in files {
+            let texture = match f.loaded_mut() {
+                Some(texture) => texture,
+                None => continue,
+            };
+            match texture.set_color_managed(enabled) {
+                Ok(true) => {
+                    let pixels = texture.pixels.clone();
+                    f.stats = None;
+                    changed.push(((f.path.clone(), f.sig), pixels));
+                },
+                Ok(false) => (),
+                Err(e) => eprintln!("failed to switch colours of {:?}: {}", f.path, e),
+            }
+        }
+        for (key, pixels) in changed {
+            self.stats_worker.request(key, pixels);
+        }
+
+        self.reload_texture().unwrap();
+        true
+    }
+
+    /// Switches the shown image between being treated as sRGB colour and
+    /// as data, like a normal map, that's shown as stored.
+    fn toggle_data(&mut self) -> bool {
+        let texture = self.shown_mut().texture_mut();
+        if let Err(e) = texture.set_data(!texture.data) {
+            eprintln!("failed to switch to data: {}", e);
+            return false;
+        }
+        self.reload_texture().unwrap();
+        true
+    }
+
+    /// The embedded profile of the shown image and what it's converted to.
+    fn profile_summary(&self) -> Option<String> {
+        let texture = self.shown().texture();
+        let name = texture.profile.as_ref()?;
+        Some(if texture.color_managed {
+            format!("profile {} -> {}", name, self.load_settings.color.display.name)
+        } else {
+            format!("profile {} (unmanaged)", name)
+        })
+    }
+
+    /// The frame being shown, for animations.
+    fn frame_summary(&self) -> Option<String> {
+        let (frame, count) = self.shown().texture().frame()?;
+        Some(format!("frame {}/{}", frame + 1, count))
+    }
+
+    /// The page being shown, for files that hold more than one image.
+    fn page_summary(&self) -> Option<String> {
+        let f = self.shown();
+        let page_count = f.texture().page_count;
+        (page_count > 1).then(|| format!("page {}/{}", f.page + 1, page_count))
+    }
+
+    /// The layer being shown, for images that have more than one.
+    fn layer_summary(&self) -> Option<String> {
+        let texture = self.shown().texture();
+        let name = texture.layers.get(texture.layer)?;
+        Some(format!("layer {}/{} {}", texture.layer + 1, texture.layers.len(), name))
+    }
+
+    /// What the shown file gets compared against: a pinned reference if
+    /// there is one, otherwise the other side of the current pair.
+    fn reference(&self) -> Option<&TextureFile> {
+        if let Some(reference) = &self.reference {
+            return Some(reference);
+        }
 
-            self.renderer.render();
+        let entry = self.current();
+        match &entry.baseline {
+            Some(_) if self.show_baseline => Some(&entry.file),
+            baseline => baseline.as_ref(),
+        }
+    }
+
+    fn comparing(&self) -> bool {
+        self.reference().is_some() && self.compare_mode != CompareMode::Off
+    }
+
+    fn split_x(&self) -> i32 {
+        (self.split * self.window_size[0] as f32) as i32
+    }
+
+    /// Draws the current image, along with the reference if comparing.
+    /// Both go through the same renderer so they share zoom, pan and
+    /// display settings.
+    fn render_images(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        // starting or stopping comparing turns spreads off and on
+        let spread_size = self.spread_size();
+        self.renderer.set_spread_size(spread_size);
+
+        // whatever's drawn has to be loaded, even if it's only just come
+        // into view
+        let layout = self.strip.enabled.then(|| self.strip_layout());
+        let drawn = match &layout {
+            Some(layout) => self.strip.visible(layout, self.window_size[1] as f32, 0.0),
+            None => self.current_spread().unwrap_or(self.current_image_index..self.current_image_index + 1),
+        };
+        for i in drawn {
+            self.load_entry(i);
+        }
+
+        let entry = &self.image_paths[self.current_image_index];
+        let (current, other) = match &entry.baseline {
+            Some(baseline) if self.show_baseline => (baseline, Some(&entry.file)),
+            baseline => (&entry.file, baseline.as_ref()),
+        };
+        let current = current.texture();
+
+        if let Some(layout) = layout {
+            let offset = self.strip.offset();
+            let images: Vec<_> = self.strip.visible(&layout, self.window_size[1] as f32, 0.0)
+                .map(|i| {
+                    let (top, height) = layout[i];
+                    (shown_file(&self.image_paths[i], self.show_baseline).texture(), top - offset, height)
+                })
+                .collect();
+            self.renderer.render_strip(&images)?;
+            return self.renderer.set_texture_data(current);
+        }
+
+        if let Some(spread) = self.current_spread().filter(|spread| spread.len() > 1) {
+            let pages: Vec<_> = self.spreads.shown_order(spread).into_iter()
+                .map(|i| shown_file(&self.image_paths[i], self.show_baseline).texture())
+                .collect();
+            self.renderer.render_spread(&pages)?;
+            // leave the renderer on the current image, as everything else expects
+            return self.renderer.set_texture_data(current);
+        }
+
+        let reference = match self.reference.as_ref().or(other) {
+            Some(reference) if self.compare_mode != CompareMode::Off => reference.texture(),
+            _ => {
+                self.renderer.render();
+                return Ok(());
+            },
+        };
+
+        let [w, h] = self.window_size;
+        match self.compare_mode {
+            CompareMode::SideBySide => {
+                let half = w / 2;
+                self.renderer.resize_window([half, h]);
+
+                unsafe { gl::Viewport(0, 0, half, h); }
+                self.renderer.set_texture_data(reference)?;
+                self.renderer.render();
+
+                unsafe { gl::Viewport(half, 0, w - half, h); }
+                self.renderer.set_texture_data(current)?;
+                self.renderer.render();
+
+                unsafe { gl::Viewport(0, 0, w, h); }
+                self.renderer.resize_window([w, h]);
+            },
+            CompareMode::Split => {
+                let split_x = self.split_x();
+                unsafe {
+                    gl::Enable(gl::SCISSOR_TEST);
+                    gl::Scissor(0, 0, split_x, h);
+                }
+                self.renderer.set_texture_data(reference)?;
+                self.renderer.render();
+
+                unsafe { gl::Scissor(split_x, 0, w - split_x, h); }
+                self.renderer.set_texture_data(current)?;
+                self.renderer.render();
+
+                unsafe { gl::Disable(gl::SCISSOR_TEST); }
+
+                self.text_renderer.queue_rect([split_x as f32 - 1.0, 0.0],
+                    [2.0, h as f32], [1.0, 1.0, 1.0, 0.8]);
+            },
+            CompareMode::Difference | CompareMode::Flicker => {
+                let op = match self.compare_mode {
+                    CompareMode::Difference => Some(ReferenceOp::Difference {
+                        gain: self.diff.gain,
+                        heatmap: self.diff.heatmap,
+                    }),
+                    _ if self.flicker.showing_reference => Some(ReferenceOp::Show),
+                    _ => None,
+                };
+
+                let view = op.map(|op| ReferenceView {
+                    texture_id: reference.texture_id,
+                    size: reference.size,
+                    offset: self.diff.align.reference_offset(current.size, reference.size),
+                    encoding: reference.encoding(),
+                    op,
+                });
+
+                self.renderer.set_reference_view(view);
+                self.renderer.render();
+                self.renderer.set_reference_view(None);
+            },
+            CompareMode::Off => unreachable!(),
+        }
+
+        Ok(())
+    }
+
+    /// The pixel under the cursor in whichever image it's over.
+    fn cursor_image_pixel(&self) -> Option<[i32;2]> {
+        // the renderer's pixels are the whole spread's, not any one image's,
+        // and the strip doesn't use them at all
+        if self.spread_size().is_some() || self.strip.enabled {
+            return None;
+        }
+
+        let [w, h] = self.window_size;
+        let [x, y] = self.cursor_position;
+        if self.comparing() && self.compare_mode == CompareMode::SideBySide {
+            let half = w / 2;
+            if x < half {
+                self.renderer.window_to_image_pixel_in([x, y], [half, h])
+            } else {
+                self.renderer.window_to_image_pixel_in([x - half, y], [w - half, h])
+            }
+        } else {
+            self.renderer.window_to_image_pixel(self.cursor_position)
+        }
+    }
+
+    /// Describes the reference being compared against, if comparing.
+    fn compare_summary(&self) -> Option<String> {
+        let reference = self.reference().filter(|_| self.comparing())?;
+        let name = reference.path.file_name().unwrap_or(reference.path.as_os_str());
+        let mut summary = format!("{} vs {}", self.compare_mode.name(), name.to_string_lossy());
+
+        match self.compare_mode {
+            CompareMode::Difference => {
+                summary += &format!(" x{}", self.diff.gain);
+                if self.diff.heatmap {
+                    summary += " heatmap";
+                }
+            },
+            CompareMode::Flicker => {
+                let showing = if self.flicker.showing_reference { "reference" } else { "current" };
+                summary += &format!(" ({})", showing);
+            },
+            _ => (),
+        }
+
+        let current_size = self.shown().texture().size;
+        let reference_size = reference.texture().size;
+        if current_size != reference_size {
+            summary += &format!(" | size mismatch {}x{} vs {}x{}, aligned {}",
+                current_size[0], current_size[1], reference_size[0], reference_size[1],
+                self.diff.align.name());
+        }
+
+        Some(summary)
+    }
+
+    fn metrics_key(&self) -> Option<MetricsKey> {
+        let reference = self.reference()?;
+        let current = self.shown();
+        Some([
+            (reference.path.clone(), reference.sig),
+            (current.path.clone(), current.sig),
+        ])
+    }
+
+    /// Collects finished diff metrics and starts computing them for the
+    /// current pair if needed. Returns whether new metrics arrived.
+    fn update_metrics(&mut self) -> bool {
+        let key = match self.metrics_key() {
+            Some(key) if self.comparing() => key,
+            _ => return false,
+        };
+
+        let mut arrived = false;
+        if let Some((job_key, receiver)) = &self.metrics_job {
+            if let Ok(result) = receiver.try_recv() {
+                self.diff_metrics = Some((job_key.clone(), result));
+                self.metrics_job = None;
+                arrived = true;
+            }
+        }
+
+        let done = matches!(&self.diff_metrics, Some((k, _)) if *k == key);
+        let running = matches!(&self.metrics_job, Some((k, _)) if *k == key);
+        if !done && !running {
+            if let Some(reference) = self.reference() {
+                let current = self.shown();
+                let receiver = metrics::spawn(reference.texture().pixels.clone(),
+                    current.texture().pixels.clone(), self.diff_threshold);
+                self.metrics_job = Some((key, receiver));
+            }
+        }
+
+        arrived
+    }
+
+    /// The diff metrics for the pair being compared, or why there aren't any.
+    fn metrics_summary(&self) -> Option<String> {
+        let key = self.metrics_key().filter(|_| self.comparing())?;
+        match &self.diff_metrics {
+            Some((k, Ok(metrics))) if *k == key => Some(metrics.summary()),
+            Some((k, Err(e))) if *k == key => Some(format!("no metrics, {}", e)),
+            _ => Some("computing metrics...".to_owned()),
+        }
+    }
+
+    fn pin_reference(&mut self) {
+        let path = self.current_image_path().clone();
+        match TextureFile::load(&path, &self.load_settings) {
+            Ok(reference) => {
+                self.reference = Some(reference);
+                if self.compare_mode == CompareMode::Off {
+                    self.compare_mode = CompareMode::SideBySide;
+                }
+            },
+            Err(e) => eprintln!("failed to pin {:?}: {}", path, e),
+        }
+    }
+
+    fn mouse_pressed(&mut self) {
+        if self.gallery.visible {
+            let count = self.image_paths.len();
+            if let Some(index) = self.gallery.index_at(self.cursor_position, count) {
+                let double_click = self.gallery.click(index);
+                self.select(index);
+                if double_click {
+                    self.gallery.visible = false;
+                }
+            }
+            return;
+        }
+
+        let near_split = (self.cursor_position[0] - self.split_x()).abs()
+            <= compare::SPLIT_GRAB_DISTANCE;
+        self.drag = if self.comparing() && self.compare_mode == CompareMode::Split && near_split {
+            Some(Drag::Split)
+        } else {
+            Some(Drag::Pan)
+        };
+    }
+
+    fn mouse_released(&mut self) {
+        self.drag = None;
+    }
+
+    /// Returns whether the window needs redrawing.
+    fn cursor_moved(&mut self, position: [i32;2]) -> bool {
+        let [old_x, old_y] = self.cursor_position;
+        self.cursor_position = position;
+
+        match self.drag {
+            Some(Drag::Split) => {
+                let w = self.window_size[0].max(1) as f32;
+                self.split = (position[0] as f32 / w).clamp(0.0, 1.0);
+                true
+            },
+            Some(Drag::Pan) if self.strip.enabled => {
+                // the strip follows the cursor, rather than easing after it
+                let offset = self.strip.offset() - (position[1] - old_y) as f32;
+                self.strip.jump_to(offset);
+                true
+            },
+            Some(Drag::Pan) => {
+                // side by side, each image only gets half the window
+                let mut view_w = self.window_size[0] as f32;
+                if self.comparing() && self.compare_mode == CompareMode::SideBySide {
+                    view_w /= 2.0;
+                }
+                let view_h = self.window_size[1] as f32;
+                let dx = 2.0 * (position[0] - old_x) as f32 / view_w;
+                let dy = -2.0 * (position[1] - old_y) as f32 / view_h;
+                self.renderer.pan_by([dx, dy]);
+                true
+            },
+            None => self.hud.visible,
         }
     }
 
     fn reload_texture(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let texture = &self.image_paths[self.current_image_index].texture;
+        self.unload_distant();
+        self.load_entry(self.current_image_index);
+        let texture = shown_file(&self.image_paths[self.current_image_index], self.show_baseline).texture();
         self.renderer.set_texture_data(texture)?;
+        let spread_size = self.spread_size();
+        self.renderer.set_spread_size(spread_size);
         Ok(())
     }
 
     fn resize_window(&mut self, size: [i32;2]) {
+        // the strip scales with the width, so stay at the same place in it
+        let widened = size[0].max(1) as f32 / self.window_size[0].max(1) as f32;
+        self.strip.jump_to(self.strip.offset() * widened);
+
         self.window_size = size;
         self.renderer.resize_window(size);
+        self.text_renderer.resize_window(size);
+        self.gallery.resize_window(size);
+        self.gallery.scroll_to(self.current_image_index, self.image_paths.len());
         
         unsafe { gl::Viewport(0, 0, size[0], size[1]); }
     }
 
     fn update(&mut self, seconds_elapsed: f32) -> bool {
+        let mut redraw = self.receive_stats();
+
+        match self.shown_mut().texture_mut().advance(seconds_elapsed) {
+            Ok(changed) => redraw |= changed,
+            Err(e) => eprintln!("failed to show the next frame: {}", e),
+        }
+
+        // vector images get rasterized again to suit the zoom
+        let zoom = self.renderer.zoom();
+        let texture = self.shown_mut().texture_mut();
+        texture.match_zoom(zoom);
+        redraw |= texture.receive_raster();
+
+        if self.comparing() && self.compare_mode == CompareMode::Flicker {
+            redraw |= self.flicker.update(seconds_elapsed);
+        }
+
+        redraw |= self.update_metrics();
+        redraw |= self.update_strip(seconds_elapsed);
+        redraw |= self.gallery.update(&self.image_paths);
+
+        if let Some(receiver) = &self.montage_job {
+            if let Ok(result) = receiver.try_recv() {
+                match result {
+                    Ok(path) => println!("wrote contact sheet to {}", path.display()),
+                    Err(e) => eprintln!("failed to write contact sheet: {}", e),
+                }
+                self.montage_job = None;
+            }
+        }
+
         self.seconds_elapsed += seconds_elapsed;
 
         if self.seconds_elapsed >= 1.0 {
             // just reset it, we don't need a stable framerate
             self.seconds_elapsed = 0.0;
 
-            let f = &mut self.image_paths[self.current_image_index];
-
-            // check if file has been modified
-            if let Ok(sig) = FileSignature::new(&f.path) {
-                if f.sig != sig {
-                    f.sig = sig;
-                    if self.reload_texture().is_ok() {
-                        return true;
+            let mut reloaded = false;
+            for f in self.image_paths[self.current_image_index].files_mut() {
+                // check if file has been modified
+                if let Ok(sig) = FileSignature::new(&f.path) {
+                    if f.sig != sig {
+                        f.sig = sig;
+                        if let Ok(texture) = Texture::from_page(&f.path, f.page, &self.load_settings) {
+                            self.stats_worker.request((f.path.clone(), f.sig), texture.pixels.clone());
+                            *f.texture_mut() = texture;
+                            f.stats = None;
+                            reloaded = true;
+                        }
                     }
                 }
             }
+
+            if reloaded && self.reload_texture().is_ok() {
+                return true;
+            }
         }
 
-        false
+        redraw
+    }
+
+    /// Stores any stats the worker has finished, returning whether the
+    /// current image's arrived while the histogram is showing.
+    fn receive_stats(&mut self) -> bool {
+        let mut redraw = false;
+        for ((path, sig), stats) in self.stats_worker.finished() {
+            for (i, entry) in self.image_paths.iter_mut().enumerate() {
+                for f in entry.files_mut() {
+                    if f.path == path && f.sig == sig {
+                        f.stats = Some(stats.clone());
+                        redraw |= self.show_histogram && i == self.current_image_index;
+                    }
+                }
+            }
+        }
+        redraw
     }
 
     fn current_image_path(&self) -> &std::path::PathBuf {
-        &self.image_paths[self.current_image_index].path
+        &self.shown().path
+    }
+
+    /// Notes on which side of a pair is being shown, or missing.
+    fn pair_summary(&self) -> Option<String> {
+        let entry = self.current();
+        if let Some(side) = entry.missing {
+            return Some(format!("MISSING {}", side.name().to_uppercase()));
+        }
+        entry.baseline.as_ref()?;
+        let side = if self.show_baseline { Side::Baseline } else { Side::Candidate };
+        Some(side.name().to_owned())
+    }
+
+    /// The verdict and note for the current image, or the note as it's
+    /// being typed.
+    fn review_summary(&self) -> Option<String> {
+        if let Some(note) = &self.note {
+            return Some(format!("note: {}_", note));
+        }
+
+        let review = self.review.get(&self.current().key())?;
+        let verdict = review.verdict.map_or("unreviewed", Verdict::name);
+        if review.note.is_empty() {
+            Some(verdict.to_owned())
+        } else {
+            Some(format!("{}: {}", verdict, review.note))
+        }
     }
 
     fn new_window_title(&self) -> String {
@@ -273,20 +1442,421 @@ impl AppData {
         let [cursor_x, cursor_y] = self.cursor_position;
         let current_index = self.current_image_index + 1;
         let total = self.image_paths.len();
-        format!("{} | {}x{} | {}/{} | ({},{})",
-            image_path, width, height, current_index, total, cursor_x, cursor_y)
+        let mut title = format!("{} | {}x{} | {}/{} | ({},{})",
+            image_path, width, height, current_index, total, cursor_x, cursor_y);
+        if let Some(summary) = self.pair_summary() {
+            title += &format!(" | {}", summary);
+        }
+        if let Some(summary) = self.review_summary() {
+            title += &format!(" | {}", summary);
+        }
+        if let Some(summary) = self.spread_summary() {
+            title += &format!(" | {}", summary);
+        }
+        if let Some(summary) = self.strip_summary() {
+            title += &format!(" | {}", summary);
+        }
+        if let Some(summary) = self.page_summary() {
+            title += &format!(" | {}", summary);
+        }
+        if let Some(summary) = self.layer_summary() {
+            title += &format!(" | {}", summary);
+        }
+        if self.display_mode != DisplayMode::Normal {
+            title += &format!(" | {}", self.display_mode.name());
+        }
+        if !self.adjustments.is_default() || self.adjustments_locked {
+            title += &format!(" | {}", self.adjustments.summary());
+        }
+        if self.adjustments_locked {
+            title += " (locked)";
+        }
+        if self.tonemap != Tonemap::Clamp {
+            title += &format!(" | {}", self.tonemap.name());
+        }
+        if let Some(summary) = self.compare_summary() {
+            title += &format!(" | {}", summary);
+        }
+        if let Some(summary) = self.metrics_summary() {
+            title += &format!(" | {}", summary);
+        }
+        title
+    }
+
+    fn hud_lines(&self) -> Vec<String> {
+        let f = self.shown();
+        let file_name = match &self.current().relative_path {
+            Some(relative_path) => relative_path.as_os_str(),
+            None => f.path.file_name().unwrap_or(f.path.as_os_str()),
+        };
+        let [width, height] = self.renderer.get_image_size();
+        let zoom = self.renderer.zoom() * 100.0;
+
+        let mut lines = vec![
+            file_name.to_string_lossy().into_owned(),
+            format!("{}/{}", self.current_image_index + 1, self.image_paths.len()),
+            format!("{}x{}  {:.0}%", width, height, zoom),
+        ];
+
+        if let Some(summary) = self.pair_summary() {
+            lines.push(summary);
+        }
+        if let Some(summary) = self.review_summary() {
+            lines.push(summary);
+        }
+        if let Some(summary) = self.spread_summary() {
+            lines.push(summary);
+        }
+        if let Some(summary) = self.strip_summary() {
+            lines.push(summary);
+        }
+        if let Some(summary) = self.page_summary() {
+            lines.push(summary);
+        }
+        if let Some(summary) = self.layer_summary() {
+            lines.push(summary);
+        }
+        if let Some(summary) = self.profile_summary() {
+            lines.push(summary);
+        }
+        if let Some(summary) = self.frame_summary() {
+            lines.push(summary);
+        }
+
+        if let Some([x, y]) = self.cursor_image_pixel() {
+            match f.texture().sample_text(x as u32, y as u32) {
+                Some(sample) => lines.push(format!("pixel ({},{}) {}", x, y, sample)),
+                None => lines.push(format!("pixel ({},{})", x, y)),
+            }
+        }
+        if f.texture().format != SampleFormat::U8 || self.tonemap != Tonemap::Clamp {
+            lines.push(format!("{}, tonemap {}", f.texture().format.name(), self.tonemap.name()));
+        }
+
+        lines.push(hud::format_file_size(f.sig.len));
+        if f.texture().data {
+            lines.push("data, not colour".to_owned());
+        }
+        if self.display_mode != DisplayMode::Normal {
+            lines.push(format!("channel: {}", self.display_mode.name()));
+        }
+        if let Some(summary) = self.compare_summary() {
+            lines.extend(summary.split(" | ").map(str::to_owned));
+        }
+        if let Some(summary) = self.metrics_summary() {
+            lines.push(summary);
+        }
+        if !self.adjustments.is_default() || self.adjustments_locked {
+            let lock = if self.adjustments_locked { " (locked)" } else { "" };
+            lines.push(format!("{}{}", self.adjustments.summary(), lock));
+        }
+        lines.push(format!("adjusting {} {:.2}", self.active_adjustment.name(),
+            self.adjustments.get(self.active_adjustment)));
+        lines
+    }
+
+    /// Carries out `action`, returning whether the window needs redrawing.
+    fn perform(&mut self, action: Action) -> bool {
+        if self.gallery.visible && self.perform_in_gallery(action) {
+            return true;
+        }
+        if self.strip.enabled && !self.gallery.visible && self.perform_in_strip(action) {
+            return true;
+        }
+
+        match action {
+            Action::ExportMontage => {
+                self.export_montage();
+                return false;
+            },
+            Action::ToggleGallery => {
+                self.gallery.visible = !self.gallery.visible;
+                self.gallery.scroll_to(self.current_image_index, self.image_paths.len());
+            },
+            Action::Next => self.cycle_right(),
+            Action::Previous => self.cycle_left(),
+            // files in an archive keep the archive's order, and stay in it
+            Action::MoveRight | Action::MoveLeft | Action::Drop
+                if archive::is_member(&self.current().file.path) => return false,
+            Action::MoveRight => self.shift_right(),
+            Action::MoveLeft => self.shift_left(),
+            Action::Drop => self.drop_current(),
+            Action::ToggleHud => self.hud.toggle(),
+            Action::CycleBackground => self.background.cycle(),
+            Action::ToggleHistogram => self.show_histogram = !self.show_histogram,
+            Action::PinReference => self.pin_reference(),
+            Action::ToggleBaseline => {
+                self.show_baseline = !self.show_baseline;
+                self.reload_texture().unwrap();
+            },
+            Action::Approve => self.set_verdict(Verdict::Approved),
+            Action::Reject => self.set_verdict(Verdict::Rejected),
+            Action::NeedsFollowUp => self.set_verdict(Verdict::NeedsFollowUp),
+            Action::EditNote => {
+                let key = self.current().key();
+                self.note = Some(self.review.get(&key).map(|r| r.note.clone()).unwrap_or_default());
+            },
+            Action::CycleCompare => self.compare_mode = self.compare_mode.next(),
+            Action::ToggleHeatmap => self.diff.heatmap = !self.diff.heatmap,
+            Action::DiffGainUp => self.diff.gain *= 2.0,
+            Action::DiffGainDown => self.diff.gain = (self.diff.gain / 2.0).max(1.0),
+            Action::ToggleCompareAlign => self.diff.align = self.diff.align.toggle(),
+            Action::CycleAdjustment => self.active_adjustment = self.active_adjustment.next(),
+            Action::AdjustUp => self.step_adjustment(1.0),
+            Action::AdjustDown => self.step_adjustment(-1.0),
+            Action::ResetAdjustments => self.set_adjustments(Adjustments::default()),
+            Action::ToggleAdjustmentLock => self.adjustments_locked = !self.adjustments_locked,
+            Action::CycleTonemap => {
+                self.tonemap = self.tonemap.next();
+                self.renderer.set_tonemap(self.tonemap);
+            },
+            Action::NextPage => return self.step_page(1),
+            Action::PreviousPage => return self.step_page(-1),
+            Action::CycleLayer => return self.cycle_layer(),
+            Action::ToggleColorManagement => return self.toggle_color_management(),
+            Action::ToggleData => return self.toggle_data(),
+            Action::CycleChannel => {
+                self.display_mode = self.display_mode.next();
+                self.renderer.set_display_mode(self.display_mode);
+            },
+            Action::ZoomIn => self.renderer.zoom_by(1.25),
+            Action::ZoomOut => self.renderer.zoom_by(0.8),
+            Action::ZoomFit => self.renderer.set_scale([1.0, 1.0]),
+            Action::ZoomActual => self.renderer.set_zoom(1.0),
+            Action::ToggleSpreads => self.toggle_spreads(),
+            Action::ToggleRightToLeft => self.spreads.right_to_left = !self.spreads.right_to_left,
+            Action::ToggleStrip => self.toggle_strip(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// The actions that mean something different in the gallery: moving
+    /// the selection around the grid, and resizing the thumbnails. Returns
+    /// whether the action was handled here.
+    fn perform_in_gallery(&mut self, action: Action) -> bool {
+        let count = self.image_paths.len();
+        let columns = self.gallery.columns();
+        let index = self.current_image_index;
+        match action {
+            Action::Next => self.select((index + 1).min(count - 1)),
+            Action::Previous => self.select(index.saturating_sub(1)),
+            Action::GridDown if index + columns < count => self.select(index + columns),
+            Action::GridUp if index >= columns => self.select(index - columns),
+            Action::GridUp | Action::GridDown => (),
+            Action::OpenSelected => self.gallery.visible = false,
+            Action::ZoomIn => self.gallery.set_cell_size(self.gallery.cell_size * 1.25, index, count),
+            Action::ZoomOut => self.gallery.set_cell_size(self.gallery.cell_size * 0.8, index, count),
+            _ => return false,
+        }
+        true
+    }
+
+    /// The actions that mean something different in the strip, where going
+    /// to the next or previous image scrolls to it, along with the strip's
+    /// own scrolling. Returns whether the action was handled here.
+    fn perform_in_strip(&mut self, action: Action) -> bool {
+        let layout = self.strip_layout();
+        let height = self.window_size[1] as f32;
+        let index = self.current_image_index;
+        match action {
+            Action::Next => if let Some(&(top, _)) = layout.get(index + 1) {
+                self.strip.scroll_to(top);
+            },
+            Action::Previous => {
+                // back to the top of this image first, if it's scrolled past
+                let top = layout[index].0;
+                let target = if self.strip.offset() > top + 1.0 || index == 0 {
+                    top
+                } else {
+                    layout[index - 1].0
+                };
+                self.strip.scroll_to(target);
+            },
+            Action::ScrollDown => self.strip.scroll_by(height / 8.0),
+            Action::ScrollUp => self.strip.scroll_by(-height / 8.0),
+            Action::ScrollPageDown => self.strip.scroll_by(height * 0.9),
+            Action::ScrollPageUp => self.strip.scroll_by(-height * 0.9),
+            _ => return false,
+        }
+        true
+    }
+
+    fn select(&mut self, index: usize) {
+        if index != self.current_image_index {
+            self.current_image_index = index;
+            self.reload_texture().unwrap();
+            self.reset_view();
+            // picking an image in the gallery goes to it in the strip
+            if self.strip.enabled {
+                let layout = self.strip_layout();
+                self.strip.jump_to(layout[index].0);
+            }
+        }
+        self.gallery.scroll_to(index, self.image_paths.len());
+    }
+
+    fn scroll_gallery(&mut self, pixels: f32) {
+        self.gallery.scroll_by(pixels, self.image_paths.len());
+    }
+
+    /// Saves a contact sheet of the whole list to the working directory,
+    /// named after the time so earlier ones aren't overwritten.
+    fn export_montage(&mut self) {
+        if self.montage_job.is_some() {
+            return;
+        }
+
+        let images = self.image_paths.iter()
+            .map(|entry| {
+                let caption = match &entry.relative_path {
+                    Some(relative_path) => relative_path.to_string_lossy().into_owned(),
+                    None => montage::caption(&entry.file.path),
+                };
+                let pixels = entry.file.loaded().map(|texture| texture.pixels.clone());
+                (caption, entry.file.path.clone(), pixels)
+            })
+            .collect();
+        let seconds = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let output = std::path::PathBuf::from(format!("contact-sheet-{}.png", seconds));
+        self.montage_job = Some(montage::spawn(images, output, MontageOptions::default()));
+    }
+
+    fn set_verdict(&mut self, verdict: Verdict) {
+        let key = self.current().key();
+        let verdict = self.review.toggle_verdict(&key, verdict);
+        if verdict == Some(Verdict::Approved) {
+            if let Err(e) = self.copy_candidate_over_baseline() {
+                eprintln!("failed to copy candidate over baseline: {}", e);
+            }
+        }
+        self.save_review();
+    }
+
+    /// For `--approve-copies`: makes the approved candidate the new
+    /// baseline, including for candidates that had no baseline yet.
+    fn copy_candidate_over_baseline(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let baseline_dir = match &self.copy_approved_to {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+
+        let entry = &mut self.image_paths[self.current_image_index];
+        let relative_path = match &entry.relative_path {
+            Some(relative_path) if entry.missing != Some(Side::Candidate) => relative_path,
+            _ => return Ok(()),
+        };
+
+        let target = baseline_dir.join(relative_path);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(&entry.file.path, &target)?;
+
+        let baseline = TextureFile::load(&target, &self.load_settings)?;
+        self.stats_worker.request((baseline.path.clone(), baseline.sig), baseline.texture().pixels.clone());
+        entry.baseline = Some(baseline);
+        entry.missing = None;
+        self.reload_texture()
+    }
+
+    fn editing_note(&self) -> bool {
+        self.note.is_some()
+    }
+
+    /// Takes a typed character while editing a note: Enter saves it and
+    /// Escape throws it away.
+    fn note_input(&mut self, c: char) {
+        let note = match &mut self.note {
+            Some(note) => note,
+            None => return,
+        };
+
+        match c {
+            '\r' | '\n' => {
+                let note = note.trim().to_owned();
+                self.note = None;
+                let key = self.current().key();
+                self.review.set_note(&key, note);
+                self.save_review();
+            },
+            '\u{1b}' => self.note = None,
+            '\u{8}' | '\u{7f}' => {
+                note.pop();
+            },
+            c if !c.is_control() => note.push(c),
+            _ => (),
+        }
+    }
+
+    fn save_review(&self) {
+        if let Err(e) = self.review.save() {
+            eprintln!("failed to save review: {}", e);
+        }
+        self.write_report();
+    }
+
+    fn write_report(&self) {
+        if let Some(path) = &self.report_path {
+            if let Err(e) = self.review.write_report(path, &self.image_paths) {
+                eprintln!("failed to write report: {}", e);
+            }
+        }
+    }
+
+    /// Puts the zoom back to the default for a newly shown image, and the
+    /// adjustments too unless they're locked.
+    fn reset_view(&mut self) {
+        match self.default_zoom {
+            Zoom::Fit => self.renderer.set_scale([1.0, 1.0]),
+            Zoom::Scale(zoom) => self.renderer.set_zoom(zoom),
+        }
+        // keep the view where it is while comparing, that's the point
+        if !self.comparing() {
+            self.renderer.set_translate([0.0, 0.0]);
+        }
+
+        if !self.adjustments_locked {
+            self.set_adjustments(Adjustments::default());
+        }
+    }
+
+    fn set_adjustments(&mut self, adjustments: Adjustments) {
+        self.adjustments = adjustments;
+        self.renderer.set_adjustments(adjustments);
+    }
+
+    fn step_adjustment(&mut self, steps: f32) {
+        let mut adjustments = self.adjustments;
+        adjustments.step(self.active_adjustment, steps);
+        self.set_adjustments(adjustments);
     }
 
     fn cycle_left(&mut self) {
-        let new_index = self.current_image_index + self.image_paths.len() - 1;
-        self.current_image_index = new_index % self.image_paths.len();
+        let count = self.image_paths.len();
+        let new_index = match self.current_spread() {
+            // the first image of the spread before
+            Some(spread) => {
+                let before = (spread.start + count - 1) % count;
+                self.spread_at(before).map_or(before, |spread| spread.start)
+            },
+            None => (self.current_image_index + count - 1) % count,
+        };
+        self.current_image_index = new_index;
         self.reload_texture().unwrap();
+        self.reset_view();
     }
 
     fn cycle_right(&mut self) {
-        let new_index = self.current_image_index + 1;
+        let new_index = match self.current_spread() {
+            Some(spread) => spread.end,
+            None => self.current_image_index + 1,
+        };
         self.current_image_index = new_index % self.image_paths.len();
         self.reload_texture().unwrap();
+        self.reset_view();
     }
 
     fn swap_image_positions(&mut self, a: usize, b: usize) {
@@ -313,6 +1883,7 @@ impl AppData {
             self.current_image_index = 0;
         }
         self.reload_texture().unwrap();
+        self.reset_view();
     }
 }
 
@@ -322,6 +1893,18 @@ struct StableAspectRatioImageRenderer {
     window_size: [i32;2],
     scale: [f32;2],
     translate: [f32;2],
+
+    // when set, the image is shown at this many window pixels per image
+    // pixel instead of being fit to the window
+    fixed_zoom: Option<f32>,
+
+    // the scale actually handed to the image renderer, after correcting
+    // for the aspect ratio
+    image_scale: [f32;2],
+
+    // when showing a two-page spread, the size of both pages together,
+    // which is what gets fit to the window
+    spread_size: Option<[i32;2]>,
 }
 
 impl StableAspectRatioImageRenderer {
@@ -330,7 +1913,10 @@ impl StableAspectRatioImageRenderer {
             image_renderer: ImageRenderer::new(),
             window_size: [1,1],
             scale: [1.0, 1.0],
-            translate: [0.0, 0.0]
+            translate: [0.0, 0.0],
+            fixed_zoom: None,
+            image_scale: [1.0, 1.0],
+            spread_size: None,
         }
     }
 
@@ -340,23 +1926,82 @@ impl StableAspectRatioImageRenderer {
     }
 
     fn recalculate_aspect_ratio(&mut self) {
-        let view_width = (self.window_size[0] as f32) * self.scale[0];
-        let view_height = (self.window_size[1] as f32) * self.scale[1];
+        let scale = self.fitted_scale(self.window_size);
+        self.image_scale = scale;
+        self.image_renderer.set_scale(scale);
+    }
+
+    fn fitted_scale(&self, window_size: [i32;2]) -> [f32;2] {
+        let view_width = (window_size[0] as f32) * self.scale[0];
+        let view_height = (window_size[1] as f32) * self.scale[1];
         let view_aspect_ratio = view_width / view_height;
 
-        let image_size = self.get_image_size();
+        let image_size = self.content_size();
         let image_aspect_ratio =
             (image_size[0] as f32) / (image_size[1] as f32);
 
-        if view_aspect_ratio < image_aspect_ratio {
+        if let Some(zoom) = self.fixed_zoom {
+            [zoom * image_size[0] as f32 / window_size[0] as f32,
+             zoom * image_size[1] as f32 / window_size[1] as f32]
+        } else if view_aspect_ratio < image_aspect_ratio {
             let yscale = view_aspect_ratio / image_aspect_ratio;
-            let scale = [self.scale[0], self.scale[1] * yscale];
-            self.image_renderer.set_scale(scale);
+            [self.scale[0], self.scale[1] * yscale]
         } else {
             let xscale = image_aspect_ratio / view_aspect_ratio;
-            let scale = [self.scale[0] * xscale, self.scale[1]];
-            self.image_renderer.set_scale(scale);
+            [self.scale[0] * xscale, self.scale[1]]
+        }
+    }
+
+    /// How many window pixels one image pixel currently covers.
+    pub fn zoom(&self) -> f32 {
+        let image_width = self.content_size()[0].max(1) as f32;
+        self.image_scale[0] * self.window_size[0] as f32 / image_width
+    }
+
+    /// Sets the scale so that one image pixel covers `zoom` window pixels.
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.fixed_zoom = Some(zoom);
+        self.recalculate_aspect_ratio();
+    }
+
+    pub fn zoom_by(&mut self, factor: f32) {
+        match self.fixed_zoom {
+            Some(zoom) => self.set_zoom(zoom * factor),
+            None => self.set_scale([self.scale[0] * factor, self.scale[1] * factor]),
+        }
+    }
+
+    pub fn pan_by(&mut self, offset: [f32;2]) {
+        let translate = [self.translate[0] + offset[0], self.translate[1] + offset[1]];
+        self.set_translate(translate);
+    }
+
+    /// Maps a position in window pixels to the image pixel under it, if any.
+    pub fn window_to_image_pixel(&self, position: [i32;2]) -> Option<[i32;2]> {
+        self.window_to_image_pixel_in(position, self.window_size)
+    }
+
+    /// Like `window_to_image_pixel`, but as if the image were being drawn
+    /// into a viewport of `window_size` rather than the whole window.
+    pub fn window_to_image_pixel_in(&self, position: [i32;2], window_size: [i32;2])
+        -> Option<[i32;2]>
+    {
+        let [window_w, window_h] = [window_size[0] as f32, window_size[1] as f32];
+        let ndc_x = 2.0 * (position[0] as f32 + 0.5) / window_w - 1.0;
+        let ndc_y = 1.0 - 2.0 * (position[1] as f32 + 0.5) / window_h;
+
+        let image_scale = self.fitted_scale(window_size);
+        let quad_x = (ndc_x - self.translate[0]) / image_scale[0];
+        let quad_y = (ndc_y - self.translate[1]) / image_scale[1];
+
+        let u = (quad_x + 1.0) / 2.0;
+        let v = (1.0 - quad_y) / 2.0;
+        if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+            return None;
         }
+
+        let [width, height] = self.content_size();
+        Some([(u * width as f32) as i32, (v * height as f32) as i32])
     }
 
     pub fn set_texture_data(&mut self, texture: &Texture)
@@ -370,6 +2015,86 @@ impl StableAspectRatioImageRenderer {
     pub fn get_image_size(&self) -> [i32; 2] {
         self.image_renderer.get_image_size()
     }
+
+    /// What's fit to the window: the image, or the spread it's part of.
+    fn content_size(&self) -> [i32; 2] {
+        self.spread_size.unwrap_or_else(|| self.get_image_size())
+    }
+
+    pub fn set_spread_size(&mut self, size: Option<[i32;2]>) {
+        if size != self.spread_size {
+            self.spread_size = size;
+            self.recalculate_aspect_ratio();
+        }
+    }
+
+    /// Draws `pages` left to right, each scaled to the same height, filling
+    /// the quad the spread size was fit into.
+    pub fn render_spread(&mut self, pages: &[&Texture]) -> Result<(), Box<dyn std::error::Error>> {
+        let widths: Vec<f32> = pages.iter()
+            .map(|page| page.size[0] as f32 / page.size[1].max(1) as f32)
+            .collect();
+        let total: f32 = widths.iter().sum();
+        let [scale_x, scale_y] = self.image_scale;
+        let [translate_x, translate_y] = self.translate;
+
+        // the quad runs from -1 to 1, and each page gets a slice of it as
+        // wide as its share of the spread
+        let mut left = -1.0;
+        for (page, width) in pages.iter().zip(widths) {
+            let fraction = width / total;
+            self.image_renderer.set_texture_data(page)?;
+            self.image_renderer.set_scale([scale_x * fraction, scale_y]);
+            self.image_renderer.set_translate([translate_x + scale_x * (left + fraction), translate_y]);
+            self.image_renderer.render();
+            left += 2.0 * fraction;
+        }
+
+        self.image_renderer.set_scale(self.image_scale);
+        self.image_renderer.set_translate(self.translate);
+        Ok(())
+    }
+
+    /// Draws images across the whole width of the window, each given as
+    /// its top and height in window pixels down from the top of it.
+    pub fn render_strip(&mut self, images: &[(&Texture, f32, f32)]) -> Result<(), Box<dyn std::error::Error>> {
+        let window_height = self.window_size[1].max(1) as f32;
+        for &(texture, top, height) in images {
+            self.image_renderer.set_texture_data(texture)?;
+            self.image_renderer.set_scale([1.0, height / window_height]);
+            // window pixels count down from the top, the quad up from the middle
+            self.image_renderer.set_translate([0.0, 1.0 - (2.0 * top + height) / window_height]);
+            self.image_renderer.render();
+        }
+
+        self.image_renderer.set_scale(self.image_scale);
+        self.image_renderer.set_translate(self.translate);
+        Ok(())
+    }
+
+    pub fn set_checkerboard(&mut self, checkerboard: Option<Checkerboard>) {
+        self.image_renderer.set_checkerboard(checkerboard);
+    }
+
+    pub fn set_display_mode(&mut self, mode: DisplayMode) {
+        self.image_renderer.set_display_mode(mode);
+    }
+
+    pub fn set_adjustments(&mut self, adjustments: Adjustments) {
+        self.image_renderer.set_adjustments(adjustments);
+    }
+
+    pub fn set_tonemap(&mut self, tonemap: Tonemap) {
+        self.image_renderer.set_tonemap(tonemap);
+    }
+
+    pub fn set_srgb_framebuffer(&mut self, srgb: bool) {
+        self.image_renderer.set_srgb_framebuffer(srgb);
+    }
+
+    pub fn set_reference_view(&mut self, view: Option<ReferenceView>) {
+        self.image_renderer.set_reference_view(view);
+    }
 }
 
 impl Renderer for StableAspectRatioImageRenderer {
@@ -379,6 +2104,7 @@ impl Renderer for StableAspectRatioImageRenderer {
 
     fn set_scale(&mut self, scale: [f32;2]) {
         self.scale = scale;
+        self.fixed_zoom = None;
         self.recalculate_aspect_ratio();
     }
 
@@ -387,3 +2113,19 @@ impl Renderer for StableAspectRatioImageRenderer {
         self.image_renderer.set_translate(translate);
     }
 }
+
+/// Opens two images and measures how far apart they are, for `viewer diff`.
+fn diff_files(a: &std::path::Path, b: &std::path::Path, threshold: u8) -> Result<DiffMetrics, String> {
+    let open = |path: &std::path::Path| texture::decode_rgba8(path, None)
+        .map_err(|e| format!("failed to open {}: {}", path.display(), e));
+    DiffMetrics::compute(&open(a)?, &open(b)?, threshold)
+}
+
+/// Which side of an entry is shown, for `AppData::shown` and the pages of
+/// a spread.
+fn shown_file(entry: &ImageEntry, show_baseline: bool) -> &TextureFile {
+    match &entry.baseline {
+        Some(baseline) if show_baseline => baseline,
+        _ => &entry.file,
+    }
+}
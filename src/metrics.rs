@@ -0,0 +1,308 @@
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+
+use serde::Serialize;
+
+/// The rectangle enclosing every pixel that changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Bounds {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Numbers for how far apart two images of the same size are.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffMetrics {
+    pub width: u32,
+    pub height: u32,
+    /// Over the RGB channels, in dB. `None` when the images are identical,
+    /// where PSNR is infinite.
+    pub psnr: Option<f64>,
+    /// Mean SSIM of the luminance, over 8x8 windows.
+    pub ssim: f64,
+    /// The largest difference in any one channel of any one pixel.
+    pub max_error: u8,
+    /// Pixels with some channel differing by more than `threshold`.
+    pub changed_pixels: u64,
+    pub changed_bounds: Option<Bounds>,
+    pub threshold: u8,
+}
+
+impl DiffMetrics {
+    pub fn compute(a: &image::RgbaImage, b: &image::RgbaImage, threshold: u8)
+        -> Result<DiffMetrics, String>
+    {
+        if a.dimensions() != b.dimensions() {
+            return Err(format!("sizes differ: {}x{} vs {}x{}",
+                a.width(), a.height(), b.width(), b.height()));
+        }
+
+        let (width, height) = a.dimensions();
+        let mut squared_error = 0.0f64;
+        let mut max_error = 0u8;
+        let mut changed_pixels = 0u64;
+        let mut bounds: Option<[u32; 4]> = None;
+
+        for (x, y, pa) in a.enumerate_pixels() {
+            let pb = b.get_pixel(x, y);
+            let mut pixel_error = 0u8;
+            for c in 0..4 {
+                let e = pa.0[c].abs_diff(pb.0[c]);
+                pixel_error = pixel_error.max(e);
+                if c < 3 {
+                    squared_error += (e as f64) * (e as f64);
+                }
+            }
+
+            max_error = max_error.max(pixel_error);
+            if pixel_error > threshold {
+                changed_pixels += 1;
+                bounds = Some(match bounds {
+                    None => [x, y, x, y],
+                    Some([x0, y0, x1, y1]) => [x0.min(x), y0.min(y), x1.max(x), y1.max(y)],
+                });
+            }
+        }
+
+        let samples = (width as f64) * (height as f64) * 3.0;
+        let mse = squared_error / samples.max(1.0);
+        let psnr = if mse == 0.0 {
+            None
+        } else {
+            Some(10.0 * (255.0 * 255.0 / mse).log10())
+        };
+
+        let changed_bounds = bounds.map(|[x0, y0, x1, y1]| Bounds {
+            x: x0, y: y0, width: x1 - x0 + 1, height: y1 - y0 + 1,
+        });
+
+        Ok(DiffMetrics {
+            width, height, psnr,
+            ssim: ssim(a, b),
+            max_error, changed_pixels, changed_bounds, threshold,
+        })
+    }
+
+    pub fn summary(&self) -> String {
+        let psnr = match self.psnr {
+            Some(psnr) => format!("{:.2} dB", psnr),
+            None => "inf".to_owned(),
+        };
+        let mut summary = format!("PSNR {} SSIM {:.4} max {} changed {}",
+            psnr, self.ssim, self.max_error, self.changed_pixels);
+        if let Some(b) = self.changed_bounds {
+            summary += &format!(" in {}x{}+{}+{}", b.width, b.height, b.x, b.y);
+        }
+        summary
+    }
+}
+
+const SSIM_WINDOW: u32 = 8;
+const SSIM_STRIDE: u32 = 4;
+
+fn luma(image: &image::RgbaImage) -> Vec<f64> {
+    image.pixels().map(|p| {
+        let [r, g, b, _] = p.0;
+        0.2126 * r as f64 + 0.7152 * g as f64 + 0.0722 * b as f64
+    }).collect()
+}
+
+/// Mean SSIM of the luminance over 8x8 windows every 4 pixels. Images
+/// smaller than a window are treated as one window.
+fn ssim(a: &image::RgbaImage, b: &image::RgbaImage) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+    let (width, height) = a.dimensions();
+    let (la, lb) = (luma(a), luma(b));
+
+    let window_w = SSIM_WINDOW.min(width);
+    let window_h = SSIM_WINDOW.min(height);
+    if window_w == 0 || window_h == 0 {
+        return 1.0;
+    }
+
+    let mut total = 0.0;
+    let mut windows = 0;
+
+    let mut y0 = 0;
+    while y0 + window_h <= height {
+        let mut x0 = 0;
+        while x0 + window_w <= width {
+            let n = (window_w * window_h) as f64;
+            let (mut sa, mut sb, mut saa, mut sbb, mut sab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in y0..y0 + window_h {
+                for x in x0..x0 + window_w {
+                    let i = (y * width + x) as usize;
+                    let (va, vb) = (la[i], lb[i]);
+                    sa += va;
+                    sb += vb;
+                    saa += va * va;
+                    sbb += vb * vb;
+                    sab += va * vb;
+                }
+            }
+
+            let (mean_a, mean_b) = (sa / n, sb / n);
+            let var_a = saa / n - mean_a * mean_a;
+            let var_b = sbb / n - mean_b * mean_b;
+            let covariance = sab / n - mean_a * mean_b;
+
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+            windows += 1;
+
+            x0 += SSIM_STRIDE;
+        }
+        y0 += SSIM_STRIDE;
+    }
+
+    total / windows as f64
+}
+
+/// Limits for `viewer diff`. Any that are set and exceeded make it fail.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tolerances {
+    pub min_psnr: Option<f64>,
+    pub min_ssim: Option<f64>,
+    pub max_error: Option<u8>,
+    pub max_changed: Option<u64>,
+}
+
+impl Tolerances {
+    /// Describes each tolerance the metrics fall outside of.
+    pub fn failures(&self, metrics: &DiffMetrics) -> Vec<String> {
+        let mut failures = Vec::new();
+
+        if let (Some(min), Some(psnr)) = (self.min_psnr, metrics.psnr) {
+            if psnr < min {
+                failures.push(format!("PSNR {:.2} dB is below {}", psnr, min));
+            }
+        }
+        if let Some(min) = self.min_ssim {
+            if metrics.ssim < min {
+                failures.push(format!("SSIM {:.4} is below {}", metrics.ssim, min));
+            }
+        }
+        if let Some(max) = self.max_error {
+            if metrics.max_error > max {
+                failures.push(format!("max error {} is above {}", metrics.max_error, max));
+            }
+        }
+        if let Some(max) = self.max_changed {
+            if metrics.changed_pixels > max {
+                failures.push(format!("{} changed pixels is above {}", metrics.changed_pixels, max));
+            }
+        }
+
+        failures
+    }
+}
+
+/// Computes metrics on a new thread, for comparing images in the viewer
+/// without holding up drawing.
+pub fn spawn(a: Arc<image::RgbaImage>, b: Arc<image::RgbaImage>, threshold: u8)
+    -> Receiver<Result<DiffMetrics, String>>
+{
+    let (sender, receiver) = channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(DiffMetrics::compute(&a, &b, threshold));
+    });
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(width: u32, height: u32, value: u8) -> image::RgbaImage {
+        image::ImageBuffer::from_pixel(width, height, image::Rgba([value, value, value, 255]))
+    }
+
+    #[test]
+    fn identical_images_pass() {
+        let a = gray(16, 16, 128);
+        let metrics = DiffMetrics::compute(&a, &a, 0).unwrap();
+        assert_eq!(metrics.psnr, None);
+        assert_eq!(metrics.max_error, 0);
+        assert_eq!(metrics.changed_pixels, 0);
+        assert_eq!(metrics.changed_bounds, None);
+        assert!((metrics.ssim - 1.0).abs() < 1e-9);
+
+        // infinite PSNR is above any minimum
+        let tolerances = Tolerances { min_psnr: Some(60.0), min_ssim: Some(1.0), max_error: Some(0), max_changed: Some(0) };
+        assert!(tolerances.failures(&metrics).is_empty());
+    }
+
+    #[test]
+    fn single_changed_pixel() {
+        let a = gray(8, 6, 100);
+        let mut b = a.clone();
+        b.put_pixel(5, 2, image::Rgba([110, 100, 100, 255]));
+
+        let metrics = DiffMetrics::compute(&a, &b, 0).unwrap();
+        assert_eq!(metrics.max_error, 10);
+        assert_eq!(metrics.changed_pixels, 1);
+        assert_eq!(metrics.changed_bounds, Some(Bounds { x: 5, y: 2, width: 1, height: 1 }));
+        assert!(metrics.psnr.is_some());
+    }
+
+    #[test]
+    fn changed_bounds_cover_every_change() {
+        let a = gray(8, 8, 0);
+        let mut b = a.clone();
+        b.put_pixel(1, 6, image::Rgba([0, 0, 50, 255]));
+        b.put_pixel(4, 3, image::Rgba([0, 0, 0, 200]));
+
+        let metrics = DiffMetrics::compute(&a, &b, 0).unwrap();
+        assert_eq!(metrics.changed_pixels, 2);
+        assert_eq!(metrics.changed_bounds, Some(Bounds { x: 1, y: 3, width: 4, height: 4 }));
+    }
+
+    #[test]
+    fn threshold_counts_only_larger_differences() {
+        let a = gray(4, 4, 100);
+        let mut b = a.clone();
+        b.put_pixel(0, 0, image::Rgba([110, 100, 100, 255]));
+        b.put_pixel(3, 3, image::Rgba([111, 100, 100, 255]));
+
+        let metrics = DiffMetrics::compute(&a, &b, 10).unwrap();
+        assert_eq!(metrics.max_error, 11);
+        assert_eq!(metrics.changed_pixels, 1);
+        assert_eq!(metrics.changed_bounds, Some(Bounds { x: 3, y: 3, width: 1, height: 1 }));
+    }
+
+    #[test]
+    fn sizes_must_match() {
+        assert!(DiffMetrics::compute(&gray(4, 4, 0), &gray(4, 5, 0), 0).is_err());
+    }
+
+    #[test]
+    fn tolerances_allow_values_on_the_limit() {
+        let a = gray(16, 16, 100);
+        let mut b = a.clone();
+        b.put_pixel(3, 3, image::Rgba([120, 100, 100, 255]));
+        let metrics = DiffMetrics::compute(&a, &b, 0).unwrap();
+        let psnr = metrics.psnr.unwrap();
+
+        let on_limit = Tolerances {
+            min_psnr: Some(psnr),
+            min_ssim: Some(metrics.ssim),
+            max_error: Some(20),
+            max_changed: Some(1),
+        };
+        assert!(on_limit.failures(&metrics).is_empty());
+
+        let past_limit = Tolerances {
+            min_psnr: Some(psnr + 0.01),
+            min_ssim: Some(metrics.ssim + 1e-6),
+            max_error: Some(19),
+            max_changed: Some(0),
+        };
+        assert_eq!(past_limit.failures(&metrics).len(), 4);
+
+        assert!(Tolerances::default().failures(&metrics).is_empty());
+    }
+}
@@ -0,0 +1,223 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+
+use crate::config::Color;
+use crate::gallery::make_thumbnail;
+use crate::text_renderer;
+
+const GLYPH_SIZE: u32 = 8;
+const ATLAS_COLUMNS: u32 = 16;
+
+/// How a contact sheet is laid out.
+#[derive(Debug, Clone, Copy)]
+pub struct MontageOptions {
+    /// Defaults to enough for a roughly square grid.
+    pub columns: Option<usize>,
+    /// Each image is scaled down to fit a square this size.
+    pub tile_size: u32,
+    pub padding: u32,
+    pub background: Color,
+    pub captions: bool,
+}
+
+impl Default for MontageOptions {
+    fn default() -> MontageOptions {
+        MontageOptions {
+            columns: None,
+            tile_size: 256,
+            padding: 8,
+            background: Color([1.0, 1.0, 1.0]),
+            captions: true,
+        }
+    }
+}
+
+/// A contact sheet being drawn on the CPU, one tile at a time so the
+/// images don't all have to be held at once.
+pub struct Montage {
+    options: MontageOptions,
+    columns: u32,
+    caption_scale: u32,
+    image: image::RgbImage,
+    glyphs: (Vec<u8>, [i32; 2]),
+}
+
+impl Montage {
+    pub fn new(count: usize, options: MontageOptions) -> Montage {
+        let count = count.max(1) as u32;
+        let columns = match options.columns {
+            Some(columns) => (columns as u32).clamp(1, count),
+            None => (count as f32).sqrt().ceil() as u32,
+        };
+        let rows = count.div_ceil(columns);
+
+        // big enough to read at a glance, without eating the tile
+        let caption_scale = if options.tile_size >= 192 { 2 } else { 1 };
+        let caption_height = caption_height(&options, caption_scale);
+
+        let cell = options.tile_size + options.padding;
+        let width = columns * cell + options.padding;
+        let height = rows * (cell + caption_height) + options.padding;
+
+        let [r, g, b] = options.background.0.map(|c| (c * 255.0).round() as u8);
+        let image = image::RgbImage::from_pixel(width, height, image::Rgb([r, g, b]));
+
+        Montage {
+            options, columns, caption_scale, image,
+            glyphs: text_renderer::glyph_atlas_pixels(),
+        }
+    }
+
+    /// Draws `img` into cell `index`, centred in its tile, with `caption`
+    /// underneath if captions are on.
+    pub fn draw_tile(&mut self, index: usize, img: &image::RgbaImage, caption: &str) {
+        let tile = self.options.tile_size;
+        let padding = self.options.padding;
+        let column = index as u32 % self.columns;
+        let row = index as u32 / self.columns;
+        let x0 = padding + column * (tile + padding);
+        let y0 = padding + row * (tile + padding + caption_height(&self.options, self.caption_scale));
+
+        let thumbnail = make_thumbnail(img, tile);
+        let (w, h) = thumbnail.dimensions();
+        let (tx, ty) = (x0 + (tile - w) / 2, y0 + (tile - h) / 2);
+        for (x, y, pixel) in thumbnail.enumerate_pixels() {
+            let [r, g, b, a] = pixel.0;
+            let out = self.image.get_pixel_mut(tx + x, ty + y);
+            let a = a as u32;
+            for (o, c) in out.0.iter_mut().zip([r, g, b]) {
+                *o = ((c as u32 * a + *o as u32 * (255 - a)) / 255) as u8;
+            }
+        }
+
+        if self.options.captions {
+            self.draw_caption(caption, [x0, y0 + tile + padding / 4]);
+        }
+    }
+
+    /// Draws `text` into the space below a tile, cut short with "..." if
+    /// it's too long to fit.
+    fn draw_caption(&mut self, text: &str, pos: [u32; 2]) {
+        let glyph = GLYPH_SIZE * self.caption_scale;
+        let max_chars = (self.options.tile_size / glyph) as usize;
+        let text: String = if text.chars().count() > max_chars {
+            let keep = max_chars.saturating_sub(3);
+            text.chars().take(keep).chain("...".chars()).take(max_chars).collect()
+        } else {
+            text.to_owned()
+        };
+
+        // dark text on light backgrounds and the other way around
+        let [r, g, b] = self.options.background.0;
+        let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        let color: u8 = if luminance > 0.5 { 0 } else { 255 };
+
+        // centre it under the tile
+        let text_width = text.chars().count() as u32 * glyph;
+        let x0 = pos[0] + (self.options.tile_size - text_width.min(self.options.tile_size)) / 2;
+
+        let (pixels, [atlas_width, _]) = &self.glyphs;
+        for (i, c) in text.chars().enumerate() {
+            let index = if c.is_ascii() { c as u32 } else { b'?' as u32 };
+            let gx = (index % ATLAS_COLUMNS) * GLYPH_SIZE;
+            let gy = (index / ATLAS_COLUMNS) * GLYPH_SIZE;
+
+            for y in 0..glyph {
+                for x in 0..glyph {
+                    let atlas_x = gx + x / self.caption_scale;
+                    let atlas_y = gy + y / self.caption_scale;
+                    if pixels[(atlas_y * *atlas_width as u32 + atlas_x) as usize] == 0 {
+                        continue;
+                    }
+
+                    let (px, py) = (x0 + i as u32 * glyph + x, pos[1] + y);
+                    if px < self.image.width() && py < self.image.height() {
+                        self.image.put_pixel(px, py, image::Rgb([color; 3]));
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn into_image(self) -> image::RgbImage {
+        self.image
+    }
+}
+
+fn caption_height(options: &MontageOptions, caption_scale: u32) -> u32 {
+    if options.captions {
+        GLYPH_SIZE * caption_scale + options.padding / 2
+    } else {
+        0
+    }
+}
+
+/// Renders a contact sheet of `images`, captioned with their file names,
+/// and saves it to `output` as PNG or JPEG going by the extension. Images
+/// that can't be read are left out with a warning, and files with several
+/// pages, like multi-page TIFFs, are shown by their first.
+pub fn write<P: AsRef<Path>>(images: &[P], output: &Path, options: MontageOptions)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    // shrunk as they're read, so only thumbnails are held until the grid
+    // size is known
+    let mut tiles = Vec::with_capacity(images.len());
+    for path in images.iter().map(AsRef::as_ref) {
+        // vector images are drawn at the tile size rather than shrunk to it
+        match crate::texture::decode_rgba8(path, Some(options.tile_size)) {
+            Ok(img) => tiles.push((make_thumbnail(&img, options.tile_size), caption(path))),
+            Err(e) => eprintln!("skipping {}: {}", path.display(), e),
+        }
+    }
+    if tiles.is_empty() {
+        return Err("none of the images could be read".into());
+    }
+
+    let mut montage = Montage::new(tiles.len(), options);
+    for (i, (img, caption)) in tiles.iter().enumerate() {
+        montage.draw_tile(i, img, caption);
+    }
+    montage.into_image().save(output)?;
+    Ok(())
+}
+
+/// Renders a contact sheet on a new thread, so the viewer carries on while
+/// it's drawn and saved. Images already in memory are used as they are,
+/// the rest are read from their files.
+pub fn spawn(images: Vec<(String, PathBuf, Option<Arc<image::RgbaImage>>)>, output: PathBuf,
+    options: MontageOptions) -> Receiver<Result<PathBuf, String>>
+{
+    let (sender, receiver) = channel();
+    std::thread::spawn(move || {
+        let tiles: Vec<_> = images.into_iter()
+            .filter_map(|(caption, path, pixels)| {
+                let img = match pixels {
+                    Some(pixels) => pixels,
+                    None => match crate::texture::decode_rgba8(&path, Some(options.tile_size)) {
+                        Ok(img) => Arc::new(make_thumbnail(&img, options.tile_size)),
+                        Err(e) => {
+                            eprintln!("skipping {}: {}", path.display(), e);
+                            return None;
+                        },
+                    },
+                };
+                Some((caption, img))
+            })
+            .collect();
+
+        let mut montage = Montage::new(tiles.len(), options);
+        for (i, (caption, img)) in tiles.iter().enumerate() {
+            montage.draw_tile(i, img, caption);
+        }
+        let result = montage.into_image().save(&output)
+            .map(|_| output)
+            .map_err(|e| e.to_string());
+        let _ = sender.send(result);
+    });
+    receiver
+}
+
+pub fn caption(path: &Path) -> String {
+    path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().into_owned()
+}
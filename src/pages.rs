@@ -0,0 +1,100 @@
+/// How many pages a file holds, given its contents: the images of a
+/// multi-page TIFF, or the sizes of an icon.
+pub fn count(data: &[u8]) -> usize {
+    if is_tiff(data) {
+        tiff_pages(data).len().max(1)
+    } else if is_ico(data) {
+        ico_entries(data).len().max(1)
+    } else {
+        1
+    }
+}
+
+/// The file rewritten to hold just page `page`, since `image` only ever
+/// decodes one. `None` means the file can be decoded as it is.
+pub fn select(data: &[u8], page: usize) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+    if is_tiff(data) {
+        if page == 0 {
+            return Ok(None);
+        }
+        // pointing the header at a later IFD makes it the first
+        let ifd = *tiff_pages(data).get(page).ok_or("no such page")?;
+        let mut selected = data.to_vec();
+        let offset = ifd as u32;
+        let bytes = if data.starts_with(b"II") { offset.to_le_bytes() } else { offset.to_be_bytes() };
+        selected[4..8].copy_from_slice(&bytes);
+        Ok(Some(selected))
+    } else if is_ico(data) {
+        // always rewritten, since the pages are ordered by size rather
+        // than as stored
+        let entry = *ico_entries(data).get(page).ok_or("no such page")?;
+        let (size, offset) = (u32_le(entry, 8) as usize, u32_le(entry, 12) as usize);
+        let image = data.get(offset..offset + size).ok_or("truncated icon")?;
+
+        let mut selected = Vec::with_capacity(22 + image.len());
+        selected.extend_from_slice(&[0, 0, 1, 0, 1, 0]);
+        selected.extend_from_slice(&entry[..12]);
+        selected.extend_from_slice(&22u32.to_le_bytes());
+        selected.extend_from_slice(image);
+        Ok(Some(selected))
+    } else if page == 0 {
+        Ok(None)
+    } else {
+        Err("no such page".into())
+    }
+}
+
+fn is_tiff(data: &[u8]) -> bool {
+    data.starts_with(b"II*\0") || data.starts_with(b"MM\0*")
+}
+
+fn is_ico(data: &[u8]) -> bool {
+    data.len() >= 6 && data[..4] == [0, 0, 1, 0]
+}
+
+fn u32_le(bytes: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap())
+}
+
+/// The offset of every IFD, in the order they're chained.
+fn tiff_pages(data: &[u8]) -> Vec<usize> {
+    let little_endian = data.starts_with(b"II");
+    let u16_at = |pos: usize| {
+        let bytes = data.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) })
+    };
+    let u32_at = |pos: usize| {
+        let bytes = data.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) } as usize)
+    };
+
+    let mut pages = Vec::new();
+    let mut ifd = u32_at(4).unwrap_or(0);
+    // a broken file can chain back on itself
+    while ifd != 0 && !pages.contains(&ifd) {
+        let entries = match u16_at(ifd) {
+            Some(entries) => entries as usize,
+            None => break,
+        };
+        pages.push(ifd);
+        ifd = u32_at(ifd + 2 + 12 * entries).unwrap_or(0);
+    }
+    pages
+}
+
+/// The 16 byte directory entries of an icon, biggest and deepest first,
+/// which is the one `image` would pick on its own.
+fn ico_entries(data: &[u8]) -> Vec<&[u8]> {
+    let count = u16::from_le_bytes([data[4], data[5]]) as usize;
+    let mut entries: Vec<_> = (0..count)
+        .map_while(|i| data.get(6 + 16 * i..22 + 16 * i))
+        .collect();
+
+    // a width or height of 0 means 256
+    let side = |b: u8| if b == 0 { 256 } else { b as u32 };
+    entries.sort_by_key(|entry| {
+        let bits_per_pixel = u16::from_le_bytes([entry[6], entry[7]]);
+        std::cmp::Reverse((bits_per_pixel, side(entry[0]) * side(entry[1])))
+    });
+    entries
+}
@@ -0,0 +1,308 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::image_list::ImageEntry;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Verdict {
+    Approved,
+    Rejected,
+    NeedsFollowUp,
+}
+
+impl Verdict {
+    pub const ALL: [Verdict; 3] = [Verdict::Approved, Verdict::Rejected, Verdict::NeedsFollowUp];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Verdict::Approved => "approved",
+            Verdict::Rejected => "rejected",
+            Verdict::NeedsFollowUp => "needs-follow-up",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Review {
+    pub verdict: Option<Verdict>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub note: String,
+}
+
+impl Review {
+    pub fn is_empty(&self) -> bool {
+        self.verdict.is_none() && self.note.is_empty()
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ReviewFile {
+    reviews: BTreeMap<String, Review>,
+}
+
+/// Verdicts and notes for the images being viewed, keyed by
+/// `ImageEntry::key`. Saved to a JSON file after every change when one
+/// was given, so a review can be picked up again later.
+#[derive(Debug, Default)]
+pub struct ReviewSession {
+    path: Option<PathBuf>,
+    reviews: BTreeMap<String, Review>,
+}
+
+impl ReviewSession {
+    /// Resumes the review saved at `path`, or starts a new one there if
+    /// the file doesn't exist yet.
+    pub fn load(path: Option<&Path>) -> Result<ReviewSession, Box<dyn std::error::Error>> {
+        let reviews = match path {
+            Some(path) if path.exists() => {
+                let text = std::fs::read_to_string(path)?;
+                serde_json::from_str::<ReviewFile>(&text)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?
+                    .reviews
+            },
+            _ => BTreeMap::new(),
+        };
+
+        Ok(ReviewSession { path: path.map(Path::to_owned), reviews })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Review> {
+        self.reviews.get(key)
+    }
+
+    /// Sets the verdict for `key`, or clears it if it was already that.
+    pub fn toggle_verdict(&mut self, key: &str, verdict: Verdict) -> Option<Verdict> {
+        let review = self.reviews.entry(key.to_owned()).or_default();
+        review.verdict = if review.verdict == Some(verdict) { None } else { Some(verdict) };
+        let verdict = review.verdict;
+        self.forget_if_empty(key);
+        verdict
+    }
+
+    pub fn set_note(&mut self, key: &str, note: String) {
+        self.reviews.entry(key.to_owned()).or_default().note = note;
+        self.forget_if_empty(key);
+    }
+
+    fn forget_if_empty(&mut self, key: &str) {
+        if self.reviews.get(key).is_some_and(Review::is_empty) {
+            self.reviews.remove(key);
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let file = ReviewFile { reviews: self.reviews.clone() };
+        std::fs::write(path, serde_json::to_string_pretty(&file)? + "\n")?;
+        Ok(())
+    }
+
+    /// Writes the report as JSON to `path` and as HTML alongside it, with
+    /// the entries in list order followed by any reviews for images that
+    /// are no longer in the list.
+    pub fn write_report(&self, path: &Path, entries: &[ImageEntry])
+        -> Result<(), Box<dyn std::error::Error>>
+    {
+        let mut rows = Vec::new();
+        let mut listed = std::collections::HashSet::new();
+
+        for entry in entries {
+            let key = entry.key();
+            let review = self.reviews.get(&key).cloned().unwrap_or_default();
+            rows.push(serde_json::json!({
+                "key": key,
+                "path": entry.file.path,
+                "baseline": entry.baseline.as_ref().map(|b| &b.path),
+                "missing": entry.missing.map(|side| side.name()),
+                "verdict": review.verdict,
+                "note": review.note,
+            }));
+            listed.insert(key);
+        }
+
+        for (key, review) in self.reviews.iter().filter(|(key, _)| !listed.contains(*key)) {
+            rows.push(serde_json::json!({
+                "key": key,
+                "verdict": review.verdict,
+                "note": review.note,
+            }));
+        }
+
+        let mut summary = serde_json::Map::new();
+        for verdict in Verdict::ALL {
+            let count = rows.iter().filter(|row| row["verdict"] == verdict.name()).count();
+            summary.insert(verdict.name().to_owned(), count.into());
+        }
+        let unreviewed = rows.iter().filter(|row| row["verdict"].is_null()).count();
+        summary.insert("unreviewed".to_owned(), unreviewed.into());
+
+        let report = serde_json::json!({ "summary": summary, "entries": rows });
+        std::fs::write(path, serde_json::to_string_pretty(&report)? + "\n")?;
+        std::fs::write(path.with_extension("html"), html_report(&report))?;
+        Ok(())
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped += "&amp;",
+            '<' => escaped += "&lt;",
+            '>' => escaped += "&gt;",
+            '"' => escaped += "&quot;",
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn html_report(report: &serde_json::Value) -> String {
+    let text = |value: &serde_json::Value| escape_html(value.as_str().unwrap_or(""));
+
+    let mut html = String::from(concat!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n",
+        "<title>Review report</title>\n<style>\n",
+        "body { font-family: sans-serif; }\n",
+        "table { border-collapse: collapse; }\n",
+        "td, th { border: 1px solid #ccc; padding: 4px 8px; text-align: left; vertical-align: top; }\n",
+        "img { max-width: 240px; max-height: 240px; }\n",
+        ".approved { background: #dfd; }\n",
+        ".rejected { background: #fdd; }\n",
+        ".needs-follow-up { background: #ffd; }\n",
+        "</style>\n</head>\n<body>\n<h1>Review report</h1>\n<p>",
+    ));
+
+    if let Some(summary) = report["summary"].as_object() {
+        let counts: Vec<_> = summary.iter().map(|(name, count)| format!("{} {}", count, name)).collect();
+        html += &escape_html(&counts.join(", "));
+    }
+
+    html += "</p>\n<table>\n<tr><th>Image</th><th>Verdict</th><th>Note</th><th>Baseline</th><th>Candidate</th></tr>\n";
+
+    for row in report["entries"].as_array().into_iter().flatten() {
+        let verdict = text(&row["verdict"]);
+        let image = |value: &serde_json::Value| match value.as_str().map(Path::new) {
+            // browsers can't see inside archives
+            Some(path) if crate::archive::is_member(path) => "(in an archive)".to_owned(),
+            Some(path) => match crate::thumbnail_cache::file_uri(path) {
+                Some(uri) => format!("<img src=\"{}\">", escape_html(&uri)),
+                None => String::new(),
+            },
+            None => String::new(),
+        };
+        // an entry missing its candidate only has the baseline as its path,
+        // and unpaired entries show up as just a candidate
+        let (missing, baseline, candidate) = match row["missing"].as_str() {
+            Some("candidate") => (" (missing candidate)".to_owned(), &row["path"], &serde_json::Value::Null),
+            Some(side) => (format!(" (missing {})", escape_html(side)), &row["baseline"], &row["path"]),
+            None => (String::new(), &row["baseline"], &row["path"]),
+        };
+
+        html += &format!(
+            "<tr class=\"{}\"><td>{}{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            verdict, text(&row["key"]), missing, verdict, text(&row["note"]),
+            image(baseline), image(candidate));
+    }
+
+    html += "</table>\n</body>\n</html>\n";
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_list::EntryPaths;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("viewer-review-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn toggling_a_verdict_twice_forgets_it() {
+        let mut session = ReviewSession::default();
+
+        assert_eq!(session.toggle_verdict("a.png", Verdict::Approved), Some(Verdict::Approved));
+        assert_eq!(session.toggle_verdict("a.png", Verdict::Rejected), Some(Verdict::Rejected));
+        assert_eq!(session.toggle_verdict("a.png", Verdict::Rejected), None);
+        assert_eq!(session.get("a.png"), None);
+
+        // a note keeps the review around without a verdict
+        session.set_note("b.png", "blurry".to_owned());
+        session.toggle_verdict("b.png", Verdict::NeedsFollowUp);
+        assert_eq!(session.toggle_verdict("b.png", Verdict::NeedsFollowUp), None);
+        assert_eq!(session.get("b.png").map(|r| r.note.as_str()), Some("blurry"));
+        session.set_note("b.png", String::new());
+        assert_eq!(session.get("b.png"), None);
+    }
+
+    #[test]
+    fn saved_reviews_load_again() {
+        let dir = temp_dir("save");
+        let path = dir.join("review.json");
+
+        let mut session = ReviewSession::load(Some(&path)).unwrap();
+        assert_eq!(session.get("a.png"), None);
+        session.toggle_verdict("a.png", Verdict::Rejected);
+        session.set_note("a.png", "banding".to_owned());
+        session.set_note("b.png", "check later".to_owned());
+        session.save().unwrap();
+
+        let loaded = ReviewSession::load(Some(&path)).unwrap();
+        assert_eq!(loaded.reviews, session.reviews);
+
+        std::fs::write(&path, "{ not json").unwrap();
+        let err = ReviewSession::load(Some(&path)).unwrap_err().to_string();
+        assert!(err.starts_with(&path.display().to_string()), "{}", err);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_in_list_order_then_orphans() {
+        let dir = temp_dir("report");
+        let entries: Vec<ImageEntry> = ["b.png", "a.png"].iter()
+            .map(|name| {
+                let path = dir.join(name);
+                std::fs::write(&path, b"").unwrap();
+                EntryPaths::single(path).entry().unwrap()
+            })
+            .collect();
+        let keys: Vec<String> = entries.iter().map(ImageEntry::key).collect();
+
+        let mut session = ReviewSession::default();
+        session.toggle_verdict(&keys[1], Verdict::Rejected);
+        session.set_note(&keys[1], "<b>too \"dark\"</b> & noisy".to_owned());
+        session.toggle_verdict("zz-gone.png", Verdict::Approved);
+        session.toggle_verdict("aa-gone.png", Verdict::Approved);
+
+        let path = dir.join("report.json");
+        session.write_report(&path, &entries).unwrap();
+
+        let report: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let order: Vec<&str> = report["entries"].as_array().unwrap().iter()
+            .map(|row| row["key"].as_str().unwrap())
+            .collect();
+        assert_eq!(order, [keys[0].as_str(), keys[1].as_str(), "aa-gone.png", "zz-gone.png"]);
+        assert_eq!(report["summary"]["approved"], 2);
+        assert_eq!(report["summary"]["rejected"], 1);
+        assert_eq!(report["summary"]["unreviewed"], 1);
+
+        let html = std::fs::read_to_string(path.with_extension("html")).unwrap();
+        assert!(html.contains("&lt;b&gt;too &quot;dark&quot;&lt;/b&gt; &amp; noisy"), "{}", html);
+        assert!(!html.contains("<b>too"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,104 @@
+use std::ops::Range;
+
+use crate::config::Config;
+
+/// Reading mode for scanned books, showing consecutive images side by side
+/// the way facing pages are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spreads {
+    pub enabled: bool,
+    /// For manga, where the earlier page of a spread is on the right.
+    pub right_to_left: bool,
+    /// Whether the first image is a cover, shown on its own so the pages
+    /// after it pair up the way they were printed.
+    pub cover: bool,
+}
+
+impl Spreads {
+    pub fn from_config(config: &Config) -> Spreads {
+        Spreads {
+            enabled: false,
+            right_to_left: config.spread_right_to_left,
+            cover: config.spread_cover,
+        }
+    }
+
+    /// Splits images of the given sizes into spreads of one or two, in
+    /// order. Wide images are already spreads, so they go on their own and
+    /// the pairing starts again after them.
+    pub fn layout(self, sizes: &[[i32; 2]]) -> Vec<Range<usize>> {
+        let wide = |i: usize| sizes[i][0] > sizes[i][1];
+
+        let mut spreads = Vec::new();
+        let mut start = 0;
+        if self.cover && !sizes.is_empty() {
+            spreads.push(0..1);
+            start = 1;
+        }
+        while start < sizes.len() {
+            let single = wide(start) || start + 1 == sizes.len() || wide(start + 1);
+            let end = if single { start + 1 } else { start + 2 };
+            spreads.push(start..end);
+            start = end;
+        }
+        spreads
+    }
+
+    /// The images in a spread, left to right as they're shown.
+    pub fn shown_order(self, spread: Range<usize>) -> Vec<usize> {
+        if self.right_to_left {
+            spread.rev().collect()
+        } else {
+            spread.collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: [i32; 2] = [600, 900];
+    const WIDE: [i32; 2] = [1200, 900];
+
+    fn spreads(cover: bool, right_to_left: bool) -> Spreads {
+        Spreads { enabled: true, right_to_left, cover }
+    }
+
+    #[test]
+    fn nothing_to_lay_out() {
+        assert!(spreads(true, false).layout(&[]).is_empty());
+        assert!(spreads(false, false).layout(&[]).is_empty());
+    }
+
+    #[test]
+    fn a_lone_cover_is_its_own_spread() {
+        for cover in [true, false] {
+            let layout = spreads(cover, false).layout(&[PAGE]);
+            assert_eq!(layout.len(), 1);
+            assert_eq!(layout[0], 0..1);
+        }
+    }
+
+    #[test]
+    fn pages_pair_up_after_the_cover() {
+        assert_eq!(spreads(true, false).layout(&[PAGE; 5]), [0..1, 1..3, 3..5]);
+        assert_eq!(spreads(false, false).layout(&[PAGE; 5]), [0..2, 2..4, 4..5]);
+    }
+
+    #[test]
+    fn wide_pages_stand_alone_and_restart_the_pairing() {
+        // the wide page is where the second page of a spread would go
+        let sizes = [PAGE, PAGE, WIDE, PAGE, PAGE];
+        assert_eq!(spreads(false, false).layout(&sizes), [0..2, 2..3, 3..5]);
+        assert_eq!(spreads(true, false).layout(&sizes), [0..1, 1..2, 2..3, 3..5]);
+        assert_eq!(spreads(false, false).layout(&[PAGE, WIDE, PAGE]), [0..1, 1..2, 2..3]);
+    }
+
+    #[test]
+    fn right_to_left_shows_later_pages_on_the_left() {
+        assert_eq!(spreads(true, true).shown_order(1..3), [2, 1]);
+        assert_eq!(spreads(true, true).shown_order(0..1), [0]);
+        assert_eq!(spreads(true, false).shown_order(1..3), [1, 2]);
+    }
+}
@@ -0,0 +1,220 @@
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+
+use crate::text_renderer::TextRenderer;
+
+pub const CHANNEL_NAMES: [&str; 5] = ["R", "G", "B", "A", "L"];
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChannelStats {
+    pub min: u8,
+    pub max: u8,
+    pub mean: f64,
+    pub std_dev: f64,
+}
+
+/// Per-channel numbers for an 8 bit RGBA image, plus Rec. 709 luminance.
+#[derive(Debug, Clone)]
+pub struct ImageStats {
+    /// R, G, B, A, luminance
+    pub channels: [ChannelStats; 5],
+    pub histograms: [[u32; 256]; 5],
+}
+
+impl ImageStats {
+    pub fn compute(img: &image::RgbaImage) -> ImageStats {
+        let mut histograms = [[0u32; 256]; 5];
+
+        for pixel in img.pixels() {
+            let [r, g, b, a] = pixel.0;
+            let luma = 0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32;
+            for (histogram, value) in histograms.iter_mut().zip([r, g, b, a, luma.round() as u8]) {
+                histogram[value as usize] += 1;
+            }
+        }
+
+        let mut channels = [ChannelStats::default(); 5];
+        for (stats, histogram) in channels.iter_mut().zip(histograms.iter()) {
+            *stats = channel_stats(histogram);
+        }
+
+        ImageStats { channels, histograms }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        CHANNEL_NAMES.iter().zip(self.channels.iter()).map(|(name, c)| {
+            format!("{} min {:3} max {:3} mean {:6.2} sd {:6.2}",
+                name, c.min, c.max, c.mean, c.std_dev)
+        }).collect()
+    }
+}
+
+fn channel_stats(histogram: &[u32; 256]) -> ChannelStats {
+    let count: u64 = histogram.iter().map(|&n| n as u64).sum();
+    if count == 0 {
+        return ChannelStats::default();
+    }
+
+    let min = histogram.iter().position(|&n| n > 0).unwrap_or(0) as u8;
+    let max = histogram.iter().rposition(|&n| n > 0).unwrap_or(0) as u8;
+
+    let mut sum = 0.0;
+    let mut sum_sq = 0.0;
+    for (value, &n) in histogram.iter().enumerate() {
+        let v = value as f64;
+        sum += v * n as f64;
+        sum_sq += v * v * n as f64;
+    }
+
+    let mean = sum / count as f64;
+    let variance = (sum_sq / count as f64 - mean * mean).max(0.0);
+    ChannelStats { min, max, mean, std_dev: variance.sqrt() }
+}
+
+/// Identifies which version of which file a set of stats belongs to, so
+/// results for a file that changed in the meantime can be thrown away.
+pub type StatsKey = (PathBuf, crate::image_list::FileSignature);
+
+/// Computes stats on a background thread, one image at a time.
+#[derive(Debug)]
+pub struct StatsWorker {
+    jobs: Sender<(StatsKey, Arc<image::RgbaImage>)>,
+    results: Receiver<(StatsKey, Arc<ImageStats>)>,
+}
+
+impl StatsWorker {
+    pub fn new() -> StatsWorker {
+        let (jobs, job_receiver) = channel::<(StatsKey, Arc<image::RgbaImage>)>();
+        let (result_sender, results) = channel();
+
+        std::thread::spawn(move || {
+            for (key, pixels) in job_receiver {
+                let stats = Arc::new(ImageStats::compute(&pixels));
+                if result_sender.send((key, stats)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        StatsWorker { jobs, results }
+    }
+
+    pub fn request(&self, key: StatsKey, pixels: Arc<image::RgbaImage>) {
+        // the worker only goes away along with the receiver, so there's
+        // nobody left to care if this fails
+        let _ = self.jobs.send((key, pixels));
+    }
+
+    pub fn finished(&self) -> impl Iterator<Item = (StatsKey, Arc<ImageStats>)> + '_ {
+        self.results.try_iter()
+    }
+}
+
+const PANEL_MARGIN: f32 = 8.0;
+const PANEL_PADDING: f32 = 6.0;
+const HISTOGRAM_HEIGHT: f32 = 100.0;
+const TEXT_SCALE: f32 = 1.0;
+
+const HISTOGRAM_COLORS: [[f32; 4]; 4] = [
+    [1.0, 0.2, 0.2, 0.5],
+    [0.2, 1.0, 0.2, 0.5],
+    [0.3, 0.4, 1.0, 0.5],
+    [1.0, 1.0, 1.0, 0.35],
+];
+
+/// Queues the histogram panel into the bottom corner on the given side
+/// of the window: the R, G, B and luminance histograms overlaid, with the
+/// numbers underneath.
+pub fn queue_panel(text_renderer: &mut TextRenderer, stats: &ImageStats,
+    window_size: [i32; 2], right_side: bool)
+{
+    let text = stats.lines().join("\n");
+    let [text_w, text_h] = TextRenderer::text_size(&text, TEXT_SCALE);
+    let width = text_w.max(256.0) + 2.0 * PANEL_PADDING;
+    let height = HISTOGRAM_HEIGHT + text_h + 3.0 * PANEL_PADDING;
+
+    let x = if right_side {
+        window_size[0] as f32 - PANEL_MARGIN - width
+    } else {
+        PANEL_MARGIN
+    };
+    let y = window_size[1] as f32 - PANEL_MARGIN - height;
+
+    text_renderer.queue_rect([x, y], [width, height], [0.0, 0.0, 0.0, 0.6]);
+
+    let histogram_x = x + PANEL_PADDING;
+    let baseline = y + PANEL_PADDING + HISTOGRAM_HEIGHT;
+
+    // R, G, B and luminance; alpha is usually all 255 and would flatten
+    // the others
+    for (histogram_index, color) in [0, 1, 2, 4].into_iter().zip(HISTOGRAM_COLORS) {
+        let histogram = &stats.histograms[histogram_index];
+        let peak = histogram.iter().copied().max().unwrap_or(0).max(1) as f32;
+        for (value, &n) in histogram.iter().enumerate() {
+            if n == 0 {
+                continue;
+            }
+            let h = HISTOGRAM_HEIGHT * n as f32 / peak;
+            text_renderer.queue_rect([histogram_x + value as f32, baseline - h], [1.0, h], color);
+        }
+    }
+
+    text_renderer.queue_text(&text, [histogram_x, baseline + PANEL_PADDING],
+        TEXT_SCALE, [1.0, 1.0, 1.0, 1.0]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn histogram(values: &[(u8, u32)]) -> [u32; 256] {
+        let mut histogram = [0; 256];
+        for &(value, n) in values {
+            histogram[value as usize] = n;
+        }
+        histogram
+    }
+
+    #[test]
+    fn empty_channels_are_all_zero() {
+        let stats = channel_stats(&[0; 256]);
+        assert_eq!((stats.min, stats.max, stats.mean, stats.std_dev), (0, 0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn a_single_value_has_no_spread() {
+        let stats = channel_stats(&histogram(&[(200, 7)]));
+        assert_eq!((stats.min, stats.max, stats.mean, stats.std_dev), (200, 200, 200.0, 0.0));
+    }
+
+    #[test]
+    fn an_even_split_lands_between_the_values() {
+        let stats = channel_stats(&histogram(&[(10, 2), (20, 2)]));
+        assert_eq!((stats.min, stats.max), (10, 20));
+        assert_eq!(stats.mean, 15.0);
+        assert_eq!(stats.std_dev, 5.0);
+
+        let stats = channel_stats(&histogram(&[(0, 3), (255, 1)]));
+        assert_eq!((stats.min, stats.max), (0, 255));
+        assert_eq!(stats.mean, 63.75);
+        assert!((stats.std_dev - 110.4185).abs() < 1e-3, "{}", stats.std_dev);
+    }
+
+    #[test]
+    fn computes_every_channel_and_luminance() {
+        let img = image::RgbaImage::from_fn(2, 1, |x, _| match x {
+            0 => image::Rgba([255, 0, 0, 255]),
+            _ => image::Rgba([255, 255, 255, 0]),
+        });
+        let stats = ImageStats::compute(&img);
+
+        assert_eq!(stats.histograms[0][255], 2);
+        assert_eq!((stats.channels[1].min, stats.channels[1].max), (0, 255));
+        assert_eq!(stats.channels[3].mean, 127.5);
+        // Rec. 709 weights red at 0.2126
+        assert_eq!(stats.histograms[4][54], 1);
+        assert_eq!(stats.histograms[4][255], 1);
+        assert_eq!(stats.histograms.map(|h| h.iter().sum::<u32>()), [2; 5]);
+    }
+}
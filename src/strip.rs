@@ -0,0 +1,161 @@
+use std::ops::Range;
+
+/// Reading mode for long images, like webtoons and tall screenshots, that
+/// stacks every image top to bottom at the window's width and scrolls
+/// through them as one strip.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Strip {
+    pub enabled: bool,
+    /// How far down the strip the top of the window is, in window pixels.
+    offset: f32,
+    /// Where scrolling is headed, which `offset` eases towards.
+    target: f32,
+}
+
+impl Strip {
+    pub fn new() -> Strip {
+        Strip { enabled: false, offset: 0.0, target: 0.0 }
+    }
+
+    /// The top and height of each image, in window pixels down the strip,
+    /// for images of the given sizes scaled to `width`.
+    pub fn layout(sizes: &[[i32; 2]], width: f32) -> Vec<(f32, f32)> {
+        let mut top = 0.0;
+        sizes.iter()
+            .map(|&[w, h]| {
+                let height = h as f32 * width / w.max(1) as f32;
+                let image = (top, height);
+                top += height;
+                image
+            })
+            .collect()
+    }
+
+    pub fn offset(&self) -> f32 {
+        self.offset
+    }
+
+    pub fn scroll_by(&mut self, pixels: f32) {
+        self.target += pixels;
+    }
+
+    pub fn scroll_to(&mut self, offset: f32) {
+        self.target = offset;
+    }
+
+    /// Goes straight to `offset` without scrolling there.
+    pub fn jump_to(&mut self, offset: f32) {
+        self.target = offset;
+        self.offset = offset;
+    }
+
+    /// Eases towards where scrolling is headed, kept within the strip.
+    /// Returns whether it moved.
+    pub fn update(&mut self, seconds: f32, layout: &[(f32, f32)], window_height: f32) -> bool {
+        let bottom = layout.last().map_or(0.0, |&(top, height)| top + height);
+        self.target = self.target.clamp(0.0, (bottom - window_height).max(0.0));
+
+        let before = self.offset;
+        // most of the way there in a tenth of a second, however far it is
+        self.offset += (self.target - self.offset) * (seconds * 20.0).min(1.0);
+        if (self.target - self.offset).abs() < 0.5 {
+            self.offset = self.target;
+        }
+        self.offset != before
+    }
+
+    /// The images that are in the window, or within `margin` pixels above
+    /// or below it.
+    pub fn visible(&self, layout: &[(f32, f32)], window_height: f32, margin: f32) -> Range<usize> {
+        let (above, below) = (self.offset - margin, self.offset + window_height + margin);
+        let start = layout.iter()
+            .position(|&(top, height)| top + height > above)
+            .unwrap_or(layout.len());
+        let end = layout.iter()
+            .rposition(|&(top, _)| top < below)
+            .map_or(start, |last| (last + 1).max(start));
+        start..end
+    }
+
+    /// The image across the middle of the window, or the last one if the
+    /// strip ends above it.
+    pub fn centered(&self, layout: &[(f32, f32)], window_height: f32) -> Option<usize> {
+        let middle = self.offset + window_height / 2.0;
+        layout.iter()
+            .position(|&(top, height)| middle < top + height)
+            .or(layout.len().checked_sub(1))
+    }
+
+    /// How far through the strip the window is, for the title and HUD.
+    pub fn progress(&self, layout: &[(f32, f32)], window_height: f32) -> f32 {
+        let bottom = layout.last().map_or(0.0, |&(top, height)| top + height);
+        let scrollable = bottom - window_height;
+        if scrollable <= 0.0 {
+            1.0
+        } else {
+            (self.offset / scrollable).clamp(0.0, 1.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(offset: f32) -> Strip {
+        let mut strip = Strip::new();
+        strip.jump_to(offset);
+        strip
+    }
+
+    #[test]
+    fn stacks_images_at_the_window_width() {
+        let layout = Strip::layout(&[[100, 200], [200, 100], [50, 50]], 100.0);
+        assert_eq!(layout, [(0.0, 200.0), (200.0, 50.0), (250.0, 100.0)]);
+    }
+
+    #[test]
+    fn an_empty_strip_shows_nothing() {
+        assert!(Strip::layout(&[], 100.0).is_empty());
+        assert_eq!(at(0.0).visible(&[], 100.0, 50.0), 0..0);
+        assert_eq!(at(0.0).centered(&[], 100.0), None);
+        assert_eq!(at(0.0).progress(&[], 100.0), 1.0);
+    }
+
+    #[test]
+    fn images_touching_the_window_edge_are_not_visible() {
+        let layout = [(0.0, 200.0), (200.0, 50.0), (250.0, 100.0)];
+        // the second image starts right where the window ends
+        assert_eq!(at(0.0).visible(&layout, 200.0, 0.0), 0..1);
+        // and the first ends right where it starts
+        assert_eq!(at(200.0).visible(&layout, 50.0, 0.0), 1..2);
+        assert_eq!(at(100.0).visible(&layout, 150.0, 0.0), 0..2);
+        // the margin reaches a window's height either way
+        assert_eq!(at(200.0).visible(&layout, 50.0, 50.0), 0..3);
+    }
+
+    #[test]
+    fn the_image_across_the_middle_is_centered() {
+        let layout = [(0.0, 200.0), (200.0, 50.0), (250.0, 100.0)];
+        assert_eq!(at(0.0).centered(&layout, 100.0), Some(0));
+        // a middle exactly on a boundary belongs to the image below it
+        assert_eq!(at(0.0).centered(&layout, 400.0), Some(1));
+        assert_eq!(at(150.0).centered(&layout, 100.0), Some(1));
+        // past the end it's the last image
+        assert_eq!(at(400.0).centered(&layout, 100.0), Some(2));
+    }
+
+    #[test]
+    fn scrolling_stays_within_the_strip() {
+        let layout = [(0.0, 200.0), (200.0, 50.0), (250.0, 100.0)];
+        let mut strip = at(0.0);
+        strip.scroll_by(1000.0);
+        // a whole second is long enough to get there
+        assert!(strip.update(1.0, &layout, 100.0));
+        assert_eq!(strip.offset(), 250.0);
+        assert_eq!(strip.progress(&layout, 100.0), 1.0);
+        strip.scroll_to(-10.0);
+        strip.update(1.0, &layout, 100.0);
+        assert_eq!(strip.offset(), 0.0);
+    }
+}
@@ -0,0 +1,73 @@
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, OnceLock};
+
+use resvg::{tiny_skia, usvg};
+
+/// A parsed SVG document, which can be rasterized at any scale.
+#[derive(Debug, Clone)]
+pub struct SvgImage {
+    tree: Arc<usvg::Tree>,
+}
+
+impl SvgImage {
+    pub fn read(path: &Path) -> Result<SvgImage, Box<dyn std::error::Error>> {
+        let data = crate::archive::read(path)?;
+        let options = usvg::Options {
+            // images the document links to are relative to it
+            resources_dir: path.parent().map(Path::to_owned),
+            fontdb: system_fonts(),
+            ..usvg::Options::default()
+        };
+        let tree = usvg::Tree::from_data(&data, &options)?;
+        Ok(SvgImage { tree: Arc::new(tree) })
+    }
+
+    /// The document's own size, in CSS pixels.
+    pub fn size(&self) -> [f32; 2] {
+        let size = self.tree.size();
+        [size.width(), size.height()]
+    }
+
+    /// The size of a raster at `scale` times the document's own size.
+    pub fn raster_size(&self, scale: f32) -> [u32; 2] {
+        self.size().map(|side| (side * scale).ceil().max(1.0) as u32)
+    }
+
+    /// Rasterizes the document at `scale` times its own size, with straight
+    /// alpha like every other decoded image.
+    pub fn render(&self, scale: f32) -> Result<image::RgbaImage, Box<dyn std::error::Error>> {
+        let [width, height] = self.raster_size(scale);
+        let mut pixmap = tiny_skia::Pixmap::new(width, height).ok_or("SVG is too big to rasterize")?;
+        resvg::render(&self.tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+        let samples = pixmap.pixels().iter()
+            .flat_map(|p| {
+                let c = p.demultiply();
+                [c.red(), c.green(), c.blue(), c.alpha()]
+            })
+            .collect();
+        Ok(image::ImageBuffer::from_raw(width, height, samples).ok_or("SVG raster is the wrong size")?)
+    }
+
+    /// Rasterizes on another thread, since big rasters take a while.
+    pub fn spawn_render(&self, scale: f32) -> Receiver<Result<image::RgbaImage, String>> {
+        let (sender, receiver) = channel();
+        let svg = self.clone();
+        std::thread::spawn(move || {
+            let _ = sender.send(svg.render(scale).map_err(|e| e.to_string()));
+        });
+        receiver
+    }
+}
+
+/// Fonts for text in documents, found once and shared, since scanning the
+/// system for them is slow.
+fn system_fonts() -> Arc<usvg::fontdb::Database> {
+    static FONTS: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+    FONTS.get_or_init(|| {
+        let mut fonts = usvg::fontdb::Database::new();
+        fonts.load_system_fonts();
+        Arc::new(fonts)
+    }).clone()
+}
@@ -0,0 +1,274 @@
+use crate::image_renderer::link_program;
+
+const GLYPH_SIZE: i32 = 8;
+const ATLAS_COLUMNS: i32 = 16;
+const ATLAS_ROWS: i32 = 8;
+
+// font8x8 has nothing to draw for DEL, so that cell of the atlas is filled
+// in solid and used for drawing plain rectangles
+const SOLID_GLYPH: usize = 127;
+
+// position, tex coords, color
+const FLOATS_PER_VERTEX: usize = 8;
+
+/// Draws text in screen space, in pixels from the top left of the window,
+/// using an embedded 8x8 bitmap font. Quads are queued up with
+/// `queue_text` and `queue_rect` and drawn together on `flush`.
+#[derive(Debug)]
+pub struct TextRenderer {
+    program: u32,
+    vertex_array: u32,
+    buffer: u32,
+    atlas: u32,
+
+    vertices: Vec<f32>,
+}
+
+impl TextRenderer {
+    pub fn new() -> TextRenderer {
+        let program = link_program(shader_code::VERTEX_SHADER_SOURCE,
+            shader_code::FRAGMENT_SHADER_SOURCE);
+        let atlas = create_glyph_atlas();
+        let (vertex_array, buffer) = create_vertex_array();
+
+        unsafe {
+            gl::UseProgram(program);
+            let location = gl::GetUniformLocation(program, c"atlas".as_ptr());
+            gl::Uniform1i(location, 0);
+        }
+
+        let mut r = TextRenderer {
+            program, vertex_array, buffer, atlas,
+            vertices: Vec::new(),
+        };
+
+        r.resize_window([1, 1]);
+
+        r
+    }
+
+    pub fn resize_window(&mut self, size: [i32; 2]) {
+        unsafe {
+            gl::UseProgram(self.program);
+            let location = gl::GetUniformLocation(self.program, c"window_size".as_ptr());
+            gl::Uniform2f(location, size[0] as f32, size[1] as f32);
+        }
+    }
+
+    /// The size in pixels `text` would take up when drawn at `scale`.
+    pub fn text_size(text: &str, scale: f32) -> [f32; 2] {
+        let glyph = GLYPH_SIZE as f32 * scale;
+        let lines = text.lines().count().max(1);
+        let columns = text.lines().map(|l| l.chars().count()).max().unwrap_or(0);
+        [columns as f32 * glyph, lines as f32 * glyph]
+    }
+
+    pub fn queue_rect(&mut self, pos: [f32; 2], size: [f32; 2], color: [f32; 4]) {
+        let (t0, t1) = glyph_tex_coords(SOLID_GLYPH);
+        // sample the middle of the solid cell so filtering can't bleed in
+        let t = [(t0[0] + t1[0]) / 2.0, (t0[1] + t1[1]) / 2.0];
+        self.queue_quad(pos, size, t, t, color);
+    }
+
+    pub fn queue_text(&mut self, text: &str, pos: [f32; 2], scale: f32, color: [f32; 4]) {
+        let glyph = GLYPH_SIZE as f32 * scale;
+        for (row, line) in text.lines().enumerate() {
+            for (column, c) in line.chars().enumerate() {
+                let index = if c.is_ascii() { c as usize } else { b'?' as usize };
+                if index == b' ' as usize {
+                    continue;
+                }
+
+                let (t0, t1) = glyph_tex_coords(index);
+                let p = [pos[0] + column as f32 * glyph, pos[1] + row as f32 * glyph];
+                self.queue_quad(p, [glyph, glyph], t0, t1, color);
+            }
+        }
+    }
+
+    fn queue_quad(&mut self, pos: [f32; 2], size: [f32; 2],
+        t0: [f32; 2], t1: [f32; 2], color: [f32; 4])
+    {
+        let [x0, y0] = pos;
+        let [x1, y1] = [pos[0] + size[0], pos[1] + size[1]];
+        let corners = [
+            [x0, y0, t0[0], t0[1]],
+            [x1, y0, t1[0], t0[1]],
+            [x1, y1, t1[0], t1[1]],
+            [x0, y0, t0[0], t0[1]],
+            [x1, y1, t1[0], t1[1]],
+            [x0, y1, t0[0], t1[1]],
+        ];
+
+        for corner in corners.iter() {
+            self.vertices.extend_from_slice(corner);
+            self.vertices.extend_from_slice(&color);
+        }
+    }
+
+    /// Draws everything queued since the last flush.
+    pub fn flush(&mut self) {
+        if self.vertices.is_empty() {
+            return;
+        }
+
+        unsafe {
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, self.atlas);
+            gl::UseProgram(self.program);
+
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.buffer);
+            let size = std::mem::size_of_val(self.vertices.as_slice()) as _;
+            gl::BufferData(gl::ARRAY_BUFFER, size,
+                self.vertices.as_ptr() as _, gl::STREAM_DRAW);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+
+            gl::BindVertexArray(self.vertex_array);
+            let count = (self.vertices.len() / FLOATS_PER_VERTEX) as _;
+            gl::DrawArrays(gl::TRIANGLES, 0, count);
+            gl::BindVertexArray(0);
+
+            gl::Disable(gl::BLEND);
+        }
+
+        self.vertices.clear();
+    }
+}
+
+impl std::ops::Drop for TextRenderer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.atlas);
+            gl::DeleteBuffers(1, &self.buffer);
+            gl::DeleteVertexArrays(1, &self.vertex_array);
+            gl::DeleteProgram(self.program);
+        }
+    }
+}
+
+fn glyph_tex_coords(index: usize) -> ([f32; 2], [f32; 2]) {
+    let column = (index as i32 % ATLAS_COLUMNS) as f32;
+    let row = (index as i32 / ATLAS_COLUMNS) as f32;
+    let w = 1.0 / ATLAS_COLUMNS as f32;
+    let h = 1.0 / ATLAS_ROWS as f32;
+    ([column * w, row * h], [(column + 1.0) * w, (row + 1.0) * h])
+}
+
+/// Renders the font into the pixels of a single channel atlas image,
+/// 16 glyphs across and 8 down.
+pub fn glyph_atlas_pixels() -> (Vec<u8>, [i32; 2]) {
+    let width = ATLAS_COLUMNS * GLYPH_SIZE;
+    let height = ATLAS_ROWS * GLYPH_SIZE;
+    let mut pixels = vec![0u8; (width * height) as usize];
+
+    for (index, glyph) in font8x8::legacy::BASIC_LEGACY.iter().enumerate() {
+        let solid = [0xffu8; 8];
+        let glyph = if index == SOLID_GLYPH { &solid } else { glyph };
+
+        let x0 = (index as i32 % ATLAS_COLUMNS) * GLYPH_SIZE;
+        let y0 = (index as i32 / ATLAS_COLUMNS) * GLYPH_SIZE;
+        for (y, bits) in glyph.iter().enumerate() {
+            for x in 0..GLYPH_SIZE {
+                if bits & (1 << x) != 0 {
+                    let i = (y0 + y as i32) * width + x0 + x;
+                    pixels[i as usize] = 255;
+                }
+            }
+        }
+    }
+
+    (pixels, [width, height])
+}
+
+fn create_glyph_atlas() -> u32 {
+    let (pixels, [width, height]) = glyph_atlas_pixels();
+
+    unsafe {
+        let mut texture = 0;
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_2D, texture);
+
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as _);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as _);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as _);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as _);
+
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::R8 as _, width, height,
+            0, gl::RED, gl::UNSIGNED_BYTE, pixels.as_ptr() as _);
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+
+        texture
+    }
+}
+
+fn create_vertex_array() -> (u32, u32) {
+    unsafe {
+        let (mut buffer, mut vertex_array) = (0, 0);
+
+        gl::GenVertexArrays(1, &mut vertex_array);
+        gl::GenBuffers(1, &mut buffer);
+
+        gl::BindVertexArray(vertex_array);
+        gl::BindBuffer(gl::ARRAY_BUFFER, buffer);
+
+        let float_size = std::mem::size_of::<f32>();
+        let stride = (FLOATS_PER_VERTEX * float_size) as _;
+
+        gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, stride, std::ptr::null());
+        gl::EnableVertexAttribArray(0);
+
+        let ptr = (2 * float_size) as _;
+        gl::VertexAttribPointer(1, 2, gl::FLOAT, gl::FALSE, stride, ptr);
+        gl::EnableVertexAttribArray(1);
+
+        let ptr = (4 * float_size) as _;
+        gl::VertexAttribPointer(2, 4, gl::FLOAT, gl::FALSE, stride, ptr);
+        gl::EnableVertexAttribArray(2);
+
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        gl::BindVertexArray(0);
+
+        (vertex_array, buffer)
+    }
+}
+
+mod shader_code {
+    pub const VERTEX_SHADER_SOURCE: &str =
+        "\
+        #version 330 core\n\
+        layout (location = 0) in vec2 pos;\n\
+        layout (location = 1) in vec2 tcoords;\n\
+        layout (location = 2) in vec4 color;\n\
+        \
+        out vec2 vtcoords;\n\
+        out vec4 vcolor;\n\
+        \
+        uniform vec2 window_size;\n\
+        \
+        void main() {\n\
+            vec2 ndc = pos / window_size * 2.0 - 1.0;\n\
+            gl_Position = vec4(ndc.x, -ndc.y, 0.0, 1.0);\n\
+            vtcoords = tcoords;\n\
+            vcolor = color;\n\
+        }\n\
+        \0";
+
+    pub const FRAGMENT_SHADER_SOURCE: &str =
+        "\
+        #version 330 core\n\
+        in vec2 vtcoords;\n\
+        in vec4 vcolor;\n\
+        out vec4 fcolor;\n\
+        \
+        uniform sampler2D atlas;\n\
+        \
+        void main() {\n\
+            float coverage = texture(atlas, vtcoords).r;\n\
+            fcolor = vec4(vcolor.rgb, vcolor.a * coverage);\n\
+        }\n\
+        \0";
+}
@@ -1,44 +1,867 @@
 use std::path::Path;
+use std::sync::Arc;
+
+use crate::color_management::{ColorManagement, EmbeddedProfile};
+use crate::config::Config;
+use crate::exr_image::{ExrImage, ExrLayer};
+use crate::svg_image::SvgImage;
+
+pub type FloatImage = image::ImageBuffer<image::Rgba<f32>, Vec<f32>>;
+
+/// Rasters of vector images wider or taller than this aren't made, however
+/// far in they're zoomed.
+const MAX_SVG_SIDE: f32 = 8192.0;
+
+/// Everything that decides how a file is turned into a texture.
+#[derive(Debug, Clone)]
+pub struct LoadSettings {
+    pub color: ColorManagement,
+    /// The resolution vector images are first rasterized at, which sets
+    /// the size they're shown at.
+    pub svg_dpi: f32,
+}
+
+impl LoadSettings {
+    pub fn from_config(config: &Config) -> LoadSettings {
+        LoadSettings {
+            color: ColorManagement::from_config(config),
+            svg_dpi: config.svg_dpi,
+        }
+    }
+}
+
+/// How the samples of an image are stored, both as decoded and on the GPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    U8,
+    U16,
+    /// Decoded as 32 bit float, but uploaded as half float.
+    F16,
+    F32,
+}
+
+impl SampleFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            SampleFormat::U8 => "8-bit",
+            SampleFormat::U16 => "16-bit",
+            SampleFormat::F16 => "half float",
+            SampleFormat::F32 => "float",
+        }
+    }
+
+    /// 8 bit colour goes in an sRGB texture, so filtering and mipmaps
+    /// happen in linear light. There's no 16 bit sRGB format, so those
+    /// get decoded in the shader instead.
+    fn internal_format(self, data: bool) -> u32 {
+        match self {
+            SampleFormat::U8 if data => gl::RGBA8,
+            SampleFormat::U8 => gl::SRGB8_ALPHA8,
+            SampleFormat::U16 => gl::RGBA16,
+            SampleFormat::F16 => gl::RGBA16F,
+            SampleFormat::F32 => gl::RGBA32F,
+        }
+    }
+}
+
+/// What sampling a texture gives the shader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Linear light, either as stored or decoded by the GPU from an sRGB
+    /// texture.
+    Linear,
+    /// sRGB encoded values the shader has to decode itself.
+    Srgb,
+    /// Data rather than colour, like a normal map or a mask, shown as it's
+    /// stored.
+    Data,
+}
+
+/// The full precision samples of an image, where that's more than the
+/// 8 bits in `Texture::pixels`.
+#[derive(Debug)]
+pub enum Samples {
+    U8,
+    U16(image::ImageBuffer<image::Rgba<u16>, Vec<u16>>),
+    F32(FloatImage),
+}
+
+#[cfg(any(feature = "avif", feature = "heic", feature = "jxl"))]
+/// One frame from a decoder outside of `image`, for formats it doesn't
+/// know about.
+#[derive(Debug)]
+pub enum FrameSamples {
+    U8(image::RgbaImage),
+    U16(image::ImageBuffer<image::Rgba<u16>, Vec<u16>>),
+    // only JPEG XL has float samples
+    #[cfg_attr(not(feature = "jxl"), allow(dead_code))]
+    F32 {
+        samples: FloatImage,
+        linear: bool,
+        /// Whether half float would lose anything.
+        full_float: bool,
+    },
+}
+
+#[cfg(any(feature = "avif", feature = "heic", feature = "jxl"))]
+#[derive(Debug)]
+pub struct DecodedFrame {
+    pub samples: FrameSamples,
+    /// How long the frame is shown for, when it's part of an animation.
+    pub duration: std::time::Duration,
+}
+
+#[cfg(any(feature = "avif", feature = "heic", feature = "jxl"))]
+/// Everything a decoder outside of `image` got out of a file.
+#[derive(Debug)]
+pub struct DecodedFrames {
+    pub frames: Vec<DecodedFrame>,
+    /// The embedded ICC profile, if there is one.
+    pub icc: Option<Vec<u8>>,
+}
+
+/// The frames of an animated image, and how far through it is.
+#[derive(Debug)]
+struct Animation {
+    /// Every frame but the shown one, which lives in the texture, along
+    /// with how many seconds each is shown for.
+    frames: Vec<(Option<Decoded>, f32)>,
+    current: usize,
+    elapsed: f32,
+}
+
+/// A vector image, and the resolution it's been rasterized at.
+#[derive(Debug)]
+struct SvgRaster {
+    image: SvgImage,
+    /// The scale `pixels` was rasterized at, relative to the document.
+    base_scale: f32,
+    /// The scale of what's in the texture, which follows the zoom.
+    scale: f32,
+    /// A raster being made in the background, and its scale.
+    job: Option<(f32, std::sync::mpsc::Receiver<Result<image::RgbaImage, String>>)>,
+}
+
+impl SvgRaster {
+    fn read(path: &Path, dpi: f32) -> Result<SvgRaster, Box<dyn std::error::Error>> {
+        let image = SvgImage::read(path)?;
+        // SVG pixels are 96 to the inch
+        let base_scale = (dpi / 96.0).min(max_svg_scale(&image));
+        Ok(SvgRaster { image, base_scale, scale: base_scale, job: None })
+    }
+
+    fn decode(&self) -> Result<Decoded, Box<dyn std::error::Error>> {
+        Ok(Decoded {
+            samples: Samples::U8, display: None,
+            format: SampleFormat::U8, linear: false, data: false,
+            pixels: Arc::new(self.image.render(self.base_scale)?),
+        })
+    }
+}
+
+/// The most a document can be scaled up by without going over
+/// `MAX_SVG_SIDE`.
+fn max_svg_scale(image: &SvgImage) -> f32 {
+    let [width, height] = image.size();
+    MAX_SVG_SIDE / width.max(height).max(1.0)
+}
 
 #[derive(Debug)]
 pub struct Texture {
     pub texture_id: u32,
     pub size: [i32; 2],
+    /// The decoded pixels the texture was uploaded from, brought down to
+    /// 8 bit sRGB for anything that needs them as bytes.
+    pub pixels: Arc<image::RgbaImage>,
+    pub samples: Samples,
+    pub format: SampleFormat,
+    /// Whether the samples are linear light rather than sRGB encoded, as
+    /// floating point images usually are.
+    pub linear: bool,
+    /// Names of the layers that can be shown, for images that have more
+    /// than one.
+    pub layers: Vec<String>,
+    pub layer: usize,
+    exr: Option<ExrImage>,
+    /// The name of the image's embedded colour profile, if it has one.
+    pub profile: Option<String>,
+    /// Whether what's shown has been converted to the display profile.
+    pub color_managed: bool,
+    /// The image as it isn't being shown, converted or not, to switch to.
+    other_colors: Option<Decoded>,
+    /// Whether the samples are data rather than colour, and so shouldn't
+    /// be decoded from sRGB.
+    pub data: bool,
+    animation: Option<Animation>,
+    svg: Option<SvgRaster>,
+    /// How many pages the file holds, for multi-page TIFFs and icons.
+    pub page_count: usize,
 }
 
 impl Texture {
-    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Texture, Box<dyn std::error::Error>> {
+    pub fn from_file<P: AsRef<Path>>(path: P, settings: &LoadSettings) -> Result<Texture, Box<dyn std::error::Error>> {
+        Texture::from_page(path, 0, settings)
+    }
+
+    /// Loads one page of a file that holds several images.
+    pub fn from_page<P: AsRef<Path>>(path: P, page: usize, settings: &LoadSettings)
+        -> Result<Texture, Box<dyn std::error::Error>>
+    {
         let texture_id = create_texture();
-        let tex_data = load_texture(path, texture_id)?;
-        Ok(Texture { texture_id, size: tex_data.size, })
+        let tex_data = match load_texture(path, page, texture_id, settings) {
+            Ok(tex_data) => tex_data,
+            Err(e) => {
+                unsafe { gl::DeleteTextures(1, &texture_id); }
+                return Err(e);
+            },
+        };
+
+        let layers = match &tex_data.exr {
+            Some(exr) if exr.layers.len() > 1 => exr.layers.iter()
+                .map(|layer| {
+                    let name = if layer.name.is_empty() { "default" } else { &layer.name };
+                    format!("{} ({})", name, layer.channel_names())
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let decoded = tex_data.decoded;
+        Ok(Texture {
+            texture_id,
+            size: tex_data.size,
+            pixels: decoded.pixels,
+            samples: decoded.samples,
+            format: decoded.format,
+            linear: decoded.linear,
+            layers,
+            layer: 0,
+            exr: tex_data.exr,
+            profile: tex_data.profile,
+            color_managed: tex_data.color_managed,
+            other_colors: tex_data.other_colors,
+            data: tex_data.data,
+            animation: tex_data.animation,
+            svg: tex_data.svg,
+            page_count: tex_data.page_count,
+        })
+    }
+
+    /// A single clear pixel, standing in for a file that couldn't be read
+    /// so there's still something to show.
+    pub fn blank() -> Texture {
+        let texture_id = create_texture();
+        let pixels = image::RgbaImage::new(1, 1);
+        upload_samples(texture_id, SampleFormat::U8.internal_format(false),
+            [1, 1], gl::UNSIGNED_BYTE, pixels.as_ptr() as _);
+        Texture {
+            texture_id,
+            size: [1, 1],
+            pixels: Arc::new(pixels),
+            samples: Samples::U8,
+            format: SampleFormat::U8,
+            linear: false,
+            layers: Vec::new(),
+            layer: 0,
+            exr: None,
+            profile: None,
+            color_managed: false,
+            other_colors: None,
+            data: false,
+            animation: None,
+            svg: None,
+            page_count: 1,
+        }
+    }
+
+    /// For vector images, starts rasterizing again in the background when
+    /// the texture's resolution is too far off for `zoom` window pixels
+    /// per image pixel. When zoomed out, mipmaps of the first raster do.
+    pub fn match_zoom(&mut self, zoom: f32) {
+        let svg = match &mut self.svg {
+            Some(svg) => svg,
+            None => return,
+        };
+        let max_scale = max_svg_scale(&svg.image).max(svg.base_scale);
+        let wanted = (svg.base_scale * zoom).clamp(svg.base_scale, max_scale);
+        let current = svg.job.as_ref().map_or(svg.scale, |&(scale, _)| scale);
+        // small changes aren't visibly blurrier, and aren't worth the work
+        if (0.8..1.25).contains(&(wanted / current)) {
+            return;
+        }
+        svg.job = Some((wanted, svg.image.spawn_render(wanted)));
+    }
+
+    /// Uploads a raster that's finished in the background, returning
+    /// whether there was one.
+    pub fn receive_raster(&mut self) -> bool {
+        use std::sync::mpsc::TryRecvError;
+
+        let svg = match &mut self.svg {
+            Some(svg) => svg,
+            None => return false,
+        };
+        let result = match svg.job.as_ref().map(|(_, receiver)| receiver.try_recv()) {
+            Some(Ok(result)) => result,
+            Some(Err(TryRecvError::Disconnected)) => Err("rasterizer stopped".to_owned()),
+            Some(Err(TryRecvError::Empty)) | None => return false,
+        };
+        let (scale, _) = svg.job.take().expect("a job just finished");
+
+        match result {
+            Ok(raster) => {
+                // only the texture gets the new resolution, the size and
+                // pixels stay as they were so zoom and picking don't change
+                upload_samples(self.texture_id, SampleFormat::U8.internal_format(self.data),
+                    [raster.width(), raster.height()], gl::UNSIGNED_BYTE, raster.as_ptr() as _);
+                svg.scale = scale;
+                true
+            },
+            Err(e) => {
+                eprintln!("failed to rasterize SVG: {}", e);
+                false
+            },
+        }
+    }
+
+    /// The frame being shown and how many there are, for animations.
+    pub fn frame(&self) -> Option<(usize, usize)> {
+        self.animation.as_ref().map(|animation| (animation.current, animation.frames.len()))
+    }
+
+    /// Moves an animation on by `seconds`, returning whether the frame
+    /// changed.
+    pub fn advance(&mut self, seconds: f32) -> Result<bool, Box<dyn std::error::Error>> {
+        let mut animation = match self.animation.take() {
+            Some(animation) => animation,
+            None => return Ok(false),
+        };
+
+        // frames that have already gone by are skipped rather than uploaded
+        animation.elapsed += seconds;
+        let mut next = animation.current;
+        while animation.elapsed >= animation.frames[next].1 {
+            animation.elapsed -= animation.frames[next].1;
+            next = (next + 1) % animation.frames.len();
+        }
+
+        let changed = next != animation.current;
+        if changed {
+            let decoded = animation.frames[next].0.take().expect("only the shown frame is missing");
+            let shown = self.show(decoded);
+            match shown {
+                Ok(shown) => {
+                    animation.frames[animation.current].0 = Some(shown);
+                    animation.current = next;
+                },
+                Err(e) => {
+                    self.animation = Some(animation);
+                    return Err(e);
+                },
+            }
+        }
+        self.animation = Some(animation);
+        Ok(changed)
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        if self.data {
+            Encoding::Data
+        } else if self.linear || self.format == SampleFormat::U8 {
+            Encoding::Linear
+        } else {
+            Encoding::Srgb
+        }
+    }
+
+    /// Marks the image as data rather than colour, or back again.
+    pub fn set_data(&mut self, data: bool) -> Result<(), Box<dyn std::error::Error>> {
+        self.data = data;
+        // only 8 bit samples go in a different kind of texture, everything
+        // else is down to the shader
+        if self.format == SampleFormat::U8 {
+            let [width, height] = self.size;
+            upload_samples(self.texture_id, self.format.internal_format(data),
+                [width as u32, height as u32], gl::UNSIGNED_BYTE, self.pixels.as_ptr() as _);
+            // which throws away any sharper raster of a vector image
+            if let Some(svg) = &mut self.svg {
+                svg.scale = svg.base_scale;
+            }
+        }
+        Ok(())
+    }
+
+    /// Switches which layer of a multi-layer image is shown.
+    pub fn set_layer(&mut self, layer: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let exr = self.exr.as_ref().ok_or("image has no layers")?;
+        let decoded = decode_layer(exr.layers.get(layer).ok_or("no such layer")?);
+        // normalised layers are data, colour layers aren't
+        self.data = decoded.data;
+        self.show(decoded)?;
+        self.layer = layer;
+        Ok(())
+    }
+
+    /// Switches between showing the image converted from its embedded
+    /// profile and showing the values as stored. Returns whether anything
+    /// changed, which it won't for images without a profile.
+    pub fn set_color_managed(&mut self, managed: bool) -> Result<bool, Box<dyn std::error::Error>> {
+        if managed == self.color_managed {
+            return Ok(false);
+        }
+        let other = match self.other_colors.take() {
+            Some(other) => other,
+            None => return Ok(false),
+        };
+        self.other_colors = Some(self.show(other)?);
+        self.color_managed = managed;
+        Ok(true)
+    }
+
+    /// Uploads `decoded` in place of what's shown, handing back what was.
+    fn show(&mut self, decoded: Decoded) -> Result<Decoded, Box<dyn std::error::Error>> {
+        upload(self.texture_id, &decoded, self.data)?;
+        Ok(Decoded {
+            pixels: std::mem::replace(&mut self.pixels, decoded.pixels),
+            samples: std::mem::replace(&mut self.samples, decoded.samples),
+            display: None,
+            data: decoded.data,
+            format: std::mem::replace(&mut self.format, decoded.format),
+            linear: std::mem::replace(&mut self.linear, decoded.linear),
+        })
+    }
+
+    /// The true sample values at a pixel, formatted to suit their type.
+    pub fn sample_text(&self, x: u32, y: u32) -> Option<String> {
+        if x >= self.size[0] as u32 || y >= self.size[1] as u32 {
+            return None;
+        }
+
+        Some(match &self.samples {
+            Samples::U8 => {
+                let [r, g, b, a] = self.pixels.get_pixel(x, y).0;
+                format!("{} {} {} {}", r, g, b, a)
+            },
+            Samples::U16(img) => {
+                let [r, g, b, a] = img.get_pixel(x, y).0;
+                format!("{} {} {} {}", r, g, b, a)
+            },
+            Samples::F32(img) => {
+                let [r, g, b, a] = img.get_pixel(x, y).0;
+                format!("{:.4} {:.4} {:.4} {:.4}", r, g, b, a)
+            },
+        })
+    }
+}
+
+impl std::ops::Drop for Texture {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.texture_id);
+        }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug)]
 struct TextureMetadata {
     size: [i32;2],
+    decoded: Decoded,
+    exr: Option<ExrImage>,
+    profile: Option<String>,
+    color_managed: bool,
+    other_colors: Option<Decoded>,
+    data: bool,
+    animation: Option<Animation>,
+    svg: Option<SvgRaster>,
+    page_count: usize,
 }
 
-fn load_texture<P: AsRef<std::path::Path>>(filename: P, texture_id: u32)
--> Result<TextureMetadata, Box<dyn std::error::Error>>
+/// An image as decoded, keeping 16 bit and floating point samples as they
+/// are rather than squashing everything to 8 bits.
+#[derive(Debug)]
+struct Decoded {
+    samples: Samples,
+    /// What to upload instead of the samples, if they aren't meant to be
+    /// shown as they are.
+    display: Option<FloatImage>,
+    format: SampleFormat,
+    linear: bool,
+    /// Set for layers that have been normalised for display.
+    data: bool,
+    pixels: Arc<image::RgbaImage>,
+}
+
+/// Decodes one page of an image along with its embedded colour profile, if
+/// it has one, and how many pages there are.
+fn decode(filename: &Path, page: usize)
+    -> Result<(Decoded, Option<EmbeddedProfile>, usize), Box<dyn std::error::Error>>
 {
-    unsafe {
-        let img = image::open(filename)?
-            .into_rgba8();
+    let extension = filename.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase);
+    let data = crate::archive::read(filename)?;
 
-        gl::BindTexture(gl::TEXTURE_2D, texture_id);
+    if extension.as_deref() == Some("hdr") {
+        let decoder = image::hdr::HdrDecoder::new(std::io::Cursor::new(&data))?;
+        let metadata = decoder.metadata();
+        let rgb = decoder.read_image_hdr()?;
+
+        let samples = rgb.iter().flat_map(|p| [p.0[0], p.0[1], p.0[2], 1.0]).collect();
+        let img = image::ImageBuffer::from_raw(metadata.width, metadata.height, samples)
+            .ok_or("HDR image is the wrong size")?;
+        let pixels = float_to_8bit(&img, true);
+        // Radiance's shared exponent format only has 8 bits of mantissa,
+        // so half float loses nothing
+        return Ok((Decoded {
+            samples: Samples::F32(img), display: None,
+            format: SampleFormat::F16, linear: true, data: false, pixels: Arc::new(pixels),
+        }, None, 1));
+    }
+
+    if extension.as_deref() == Some("psd") {
+        // the flattened composite Photoshop saves along with the layers
+        let psd = psd::Psd::from_bytes(&data)?;
+        let img = image::ImageBuffer::from_raw(psd.width(), psd.height(), psd.rgba())
+            .ok_or("PSD composite is the wrong size")?;
+        return Ok((Decoded {
+            samples: Samples::U8, display: None,
+            format: SampleFormat::U8, linear: false, data: false,
+            pixels: Arc::new(img),
+        }, None, 1));
+    }
 
-        let data = img.as_ptr() as _;
-        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA as _,
-            img.width() as _, img.height() as _,
-            0, gl::RGBA, gl::UNSIGNED_BYTE, data);
+    let page_count = crate::pages::count(&data);
+    let data = match crate::pages::select(&data, page)? {
+        Some(selected) => selected,
+        None => data,
+    };
+    let img = image::load_from_memory_with_format(&data, image::ImageFormat::from_path(filename)?)?;
+    let profile = match EmbeddedProfile::find(&data) {
+        Ok(profile) => profile,
+        Err(e) => {
+            eprintln!("ignoring colour profile in {}: {}", filename.display(), e);
+            None
+        },
+    };
+
+    let decoded = match img.color() {
+        image::ColorType::L16 | image::ColorType::La16
+        | image::ColorType::Rgb16 | image::ColorType::Rgba16 => {
+            let img = img.into_rgba16();
+            Decoded {
+                pixels: Arc::new(u16_to_8bit(&img)),
+                samples: Samples::U16(img), display: None,
+                format: SampleFormat::U16, linear: false, data: false,
+            }
+        },
+        _ => Decoded {
+            samples: Samples::U8, display: None,
+            format: SampleFormat::U8, linear: false, data: false,
+            pixels: Arc::new(img.into_rgba8()),
+        },
+    };
+    Ok((decoded, profile, page_count))
+}
+
+/// Converts decoded samples from their embedded profile to the display's.
+fn convert_colors(decoded: &Decoded, profile: &EmbeddedProfile, color: &ColorManagement)
+    -> Result<Decoded, Box<dyn std::error::Error>>
+{
+    match &decoded.samples {
+        Samples::U8 => Ok(Decoded {
+            samples: Samples::U8, display: None,
+            format: decoded.format, linear: decoded.linear, data: decoded.data,
+            pixels: Arc::new(profile.convert_rgba8(&decoded.pixels, &color.display)?),
+        }),
+        Samples::U16(img) => {
+            let img = profile.convert_rgba16(img, &color.display)?;
+            Ok(Decoded {
+                pixels: Arc::new(u16_to_8bit(&img)),
+                samples: Samples::U16(img), display: None,
+                format: decoded.format, linear: decoded.linear, data: decoded.data,
+            })
+        },
+        Samples::F32(_) => Err("colour profiles on float images aren't supported".into()),
+    }
+}
+
+fn decode_layer(layer: &ExrLayer) -> Decoded {
+    let layer_pixels = layer.to_pixels();
+    let pixels = float_to_8bit(&layer_pixels.display, layer_pixels.linear);
+    let format = if layer_pixels.full_float { SampleFormat::F32 } else { SampleFormat::F16 };
+    Decoded {
+        samples: Samples::F32(layer_pixels.samples),
+        display: Some(layer_pixels.display),
+        format,
+        linear: layer_pixels.linear,
+        data: !layer_pixels.linear,
+        pixels: Arc::new(pixels),
+    }
+}
+
+#[cfg(any(feature = "avif", feature = "heic", feature = "jxl"))]
+fn decode_frame(frame: FrameSamples) -> Decoded {
+    match frame {
+        FrameSamples::U8(img) => Decoded {
+            samples: Samples::U8, display: None,
+            format: SampleFormat::U8, linear: false, data: false,
+            pixels: Arc::new(img),
+        },
+        FrameSamples::U16(img) => Decoded {
+            pixels: Arc::new(u16_to_8bit(&img)),
+            samples: Samples::U16(img), display: None,
+            format: SampleFormat::U16, linear: false, data: false,
+        },
+        FrameSamples::F32 { samples, linear, full_float } => Decoded {
+            pixels: Arc::new(float_to_8bit(&samples, linear)),
+            samples: Samples::F32(samples), display: None,
+            format: if full_float { SampleFormat::F32 } else { SampleFormat::F16 },
+            linear, data: false,
+        },
+    }
+}
+
+/// The first frame of an image, its colour profile, and the rest of the
+/// frames if it's animated.
+#[cfg(any(feature = "avif", feature = "heic", feature = "jxl"))]
+type FirstFrame = (Decoded, Option<EmbeddedProfile>, Option<Animation>);
+
+#[cfg(any(feature = "avif", feature = "heic", feature = "jxl"))]
+/// Turns frames from another decoder into the first frame and, if there
+/// are more, the animation.
+fn decode_frames(decoded: DecodedFrames) -> Result<FirstFrame, Box<dyn std::error::Error>> {
+    let mut frames = decoded.frames.into_iter().map(|frame| {
+        // like browsers, treat frames too short to see as unspecified
+        let seconds = frame.duration.as_secs_f32();
+        let seconds = if seconds <= 0.01 { 0.1 } else { seconds };
+        (Some(decode_frame(frame.samples)), seconds)
+    }).collect::<Vec<_>>();
+
+    let first = frames.first_mut().and_then(|(frame, _)| frame.take()).ok_or("image has no frames")?;
+    if frames.len() > 1 {
+        // converting every frame's colours, and keeping both versions, costs
+        // too much for animations, so they're left as they are
+        let animation = Animation { frames, current: 0, elapsed: 0.0 };
+        return Ok((first, None, Some(animation)));
+    }
+
+    let profile = match decoded.icc.map(|icc| EmbeddedProfile::parse(&icc)).transpose() {
+        Ok(profile) => profile,
+        Err(e) => {
+            eprintln!("ignoring colour profile: {}", e);
+            None
+        },
+    };
+    Ok((first, profile, None))
+}
+
+fn u16_to_8bit(img: &image::ImageBuffer<image::Rgba<u16>, Vec<u16>>) -> image::RgbaImage {
+    image::ImageBuffer::from_fn(img.width(), img.height(), |x, y| {
+        image::Rgba(img.get_pixel(x, y).0.map(|c| (c >> 8) as u8))
+    })
+}
+
+/// Clamps float samples into 8 bits, encoding them as sRGB if they're
+/// linear.
+pub fn float_to_8bit(img: &FloatImage, linear: bool) -> image::RgbaImage {
+    let encode = |c: f32| {
+        let c = c.clamp(0.0, 1.0);
+        let c = if !linear || c <= 0.0031308 {
+            if linear { c * 12.92 } else { c }
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        };
+        (c * 255.0).round() as u8
+    };
+    image::ImageBuffer::from_fn(img.width(), img.height(), |x, y| {
+        let [r, g, b, a] = img.get_pixel(x, y).0;
+        image::Rgba([encode(r), encode(g), encode(b), (a.clamp(0.0, 1.0) * 255.0).round() as u8])
+    })
+}
+
+fn upload(texture_id: u32, decoded: &Decoded, data: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let (data_type, samples) = match (&decoded.display, &decoded.samples) {
+        (Some(display), _) => (gl::FLOAT, display.as_ptr() as *const std::ffi::c_void),
+        (None, Samples::U8) => (gl::UNSIGNED_BYTE, decoded.pixels.as_ptr() as _),
+        (None, Samples::U16(img)) => (gl::UNSIGNED_SHORT, img.as_ptr() as _),
+        (None, Samples::F32(img)) => (gl::FLOAT, img.as_ptr() as _),
+    };
+    let (width, height) = decoded.pixels.dimensions();
+    upload_samples(texture_id, decoded.format.internal_format(data),
+        [width, height], data_type, samples);
+    Ok(())
+}
+
+fn upload_samples(texture_id: u32, internal_format: u32, [width, height]: [u32; 2],
+    data_type: u32, samples: *const std::ffi::c_void)
+{
+    unsafe {
+        gl::BindTexture(gl::TEXTURE_2D, texture_id);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, internal_format as _,
+            width as _, height as _,
+            0, gl::RGBA, data_type, samples);
 
         gl::GenerateMipmap(gl::TEXTURE_2D);
+    }
+}
+
+/// A file decoded as far as it goes without the GPU or the display's
+/// colour profile.
+struct DecodedFile {
+    decoded: Decoded,
+    exr: Option<ExrImage>,
+    profile: Option<EmbeddedProfile>,
+    animation: Option<Animation>,
+    svg: Option<SvgRaster>,
+    page_count: usize,
+}
+
+/// Headers are near the start of a file, and nearly all of them fit in
+/// this much of it.
+const HEADER_BYTES: u64 = 256 * 1024;
+
+/// The size the first page of a file loads at, from its header, for laying
+/// images out before they're loaded.
+pub fn probe_size(path: &Path, settings: &LoadSettings) -> Result<[i32; 2], Box<dyn std::error::Error>> {
+    let extension = path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase);
+    if let Some("svg" | "svgz") = extension.as_deref() {
+        // parsed, but not rasterized
+        let raster = SvgRaster::read(path, settings.svg_dpi)?;
+        let [width, height] = raster.image.raster_size(raster.base_scale);
+        return Ok([width as i32, height as i32]);
+    }
 
-        let size = [img.width() as i32, img.height() as i32];
+    let start = crate::archive::read_start(path, HEADER_BYTES)?;
+    let size = match header_size(path, extension.as_deref(), &start) {
+        Err(_) if start.len() as u64 == HEADER_BYTES => {
+            header_size(path, extension.as_deref(), &crate::archive::read(path)?)
+        },
+        size => size,
+    };
+    let [width, height] = size?;
+    Ok([width as i32, height as i32])
+}
+
+fn header_size(path: &Path, extension: Option<&str>, data: &[u8]) -> Result<[u32; 2], Box<dyn std::error::Error>> {
+    match extension {
+        Some("exr") => ExrImage::size(data),
+        Some("psd") => {
+            // the height and then the width, after the signature, version,
+            // reserved bytes and channel count
+            let field = |at: usize| data.get(at..at + 4)
+                .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+                .ok_or("PSD header is cut short");
+            Ok([field(18)?, field(14)?])
+        },
+        #[cfg(feature = "avif")]
+        Some("avif") => crate::heif_image::size(data),
+        #[cfg(feature = "heic")]
+        Some("heic" | "heif") => crate::heif_image::size(data),
+        #[cfg(feature = "jxl")]
+        Some("jxl") => crate::jxl_image::size(data),
+        _ => {
+            let mut reader = image::io::Reader::new(std::io::Cursor::new(data));
+            reader.set_format(image::ImageFormat::from_path(path)?);
+            let (width, height) = reader.into_dimensions()?;
+            Ok([width, height])
+        },
+    }
+}
 
-        Ok(TextureMetadata { size })
+/// Decodes the first page of anything the viewer can show into 8 bit
+/// sRGB, as stored without converting colour profiles. This is for the
+/// commands and background threads, which have no window to decode for.
+/// Vector images are rasterized to fit a square `fit` pixels on a side if
+/// it's given, rather than at their own size.
+pub fn decode_rgba8(path: &Path, fit: Option<u32>) -> Result<image::RgbaImage, Box<dyn std::error::Error>> {
+    let extension = path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase);
+    if let (Some(side), Some("svg" | "svgz")) = (fit, extension.as_deref()) {
+        let svg = SvgImage::read(path)?;
+        let [width, height] = svg.size();
+        let scale = (side as f32 / width.max(height).max(1.0)).min(max_svg_scale(&svg));
+        return svg.render(scale);
     }
+
+    let file = decode_file(path, 0, 96.0)?;
+    Ok(Arc::unwrap_or_clone(file.decoded.pixels))
+}
+
+fn decode_file(filename: &Path, page: usize, svg_dpi: f32) -> Result<DecodedFile, Box<dyn std::error::Error>> {
+    let extension = filename.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase);
+
+    let mut svg = None;
+    let mut page_count = 1;
+    let (decoded, exr, profile, animation) = match extension.as_deref() {
+        Some("exr") => {
+            let exr = ExrImage::read(filename)?;
+            (decode_layer(&exr.layers[0]), Some(exr), None, None)
+        },
+        Some("svg" | "svgz") => {
+            let raster = SvgRaster::read(filename, svg_dpi)?;
+            let decoded = raster.decode()?;
+            svg = Some(raster);
+            (decoded, None, None, None)
+        },
+        #[cfg(feature = "avif")]
+        Some("avif") => {
+            let (decoded, profile, animation) = decode_frames(crate::heif_image::read(filename)?)?;
+            (decoded, None, profile, animation)
+        },
+        #[cfg(feature = "heic")]
+        Some("heic" | "heif") => {
+            let (decoded, profile, animation) = decode_frames(crate::heif_image::read(filename)?)?;
+            (decoded, None, profile, animation)
+        },
+        #[cfg(feature = "jxl")]
+        Some("jxl") => {
+            let (decoded, profile, animation) = decode_frames(crate::jxl_image::read(filename)?)?;
+            (decoded, None, profile, animation)
+        },
+        _ => {
+            let (decoded, profile, pages) = decode(filename, page)?;
+            page_count = pages;
+            (decoded, None, profile, None)
+        },
+    };
+    Ok(DecodedFile { decoded, exr, profile, animation, svg, page_count })
+}
+
+fn load_texture<P: AsRef<std::path::Path>>(filename: P, page: usize, texture_id: u32, settings: &LoadSettings)
+-> Result<TextureMetadata, Box<dyn std::error::Error>>
+{
+    let filename = filename.as_ref();
+    let color = &settings.color;
+    let DecodedFile { decoded, exr, profile, animation, svg, page_count } =
+        decode_file(filename, page, settings.svg_dpi)?;
+
+    // both versions are kept, so switching between them doesn't mean
+    // decoding the file again
+    let converted = profile.as_ref().and_then(|profile| {
+        convert_colors(&decoded, profile, color)
+            .map_err(|e| eprintln!("failed to convert {} from {}: {}",
+                filename.display(), profile.name, e))
+            .ok()
+    });
+    let (decoded, other_colors, color_managed) = match converted {
+        Some(converted) if color.enabled => (converted, Some(decoded), true),
+        Some(converted) => (decoded, Some(converted), false),
+        None => (decoded, None, false),
+    };
+
+    let data = decoded.data || color.is_data(filename);
+    upload(texture_id, &decoded, data)?;
+
+    let (width, height) = decoded.pixels.dimensions();
+    Ok(TextureMetadata {
+        size: [width as i32, height as i32],
+        decoded,
+        exr,
+        profile: profile.map(|profile| profile.name),
+        color_managed,
+        other_colors,
+        data,
+        animation,
+        svg,
+        page_count,
+    })
 }
 
 pub fn create_texture() -> u32 {
@@ -49,9 +872,156 @@ pub fn create_texture() -> u32 {
         
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as _);
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as _);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as _);
+        // mipmapped when zoomed out, but pixels stay sharp when zoomed in
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as _);
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as _);
 
         texture
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("viewer-test-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn decodes_exr_without_a_window() {
+        let path = temp_path("decode.exr");
+        exr::prelude::write_rgba_file(&path, 3, 2, |x, _| (x as f32 / 2.0, 0.0, 1.0, 1.0)).unwrap();
+        let img = decode_rgba8(&path, None);
+        std::fs::remove_file(&path).unwrap();
+
+        let img = img.unwrap();
+        assert_eq!(img.dimensions(), (3, 2));
+        assert_eq!(img.get_pixel(0, 0).0, [0, 0, 255, 255]);
+        assert_eq!(img.get_pixel(2, 1).0, [255, 0, 255, 255]);
+    }
+
+    #[test]
+    fn rasterizes_svg_to_fit() {
+        let path = temp_path("fit.svg");
+        std::fs::write(&path, concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="50">"#,
+            r#"<rect width="100" height="50" fill="red"/></svg>"#)).unwrap();
+        let fitted = decode_rgba8(&path, Some(256));
+        let own_size = decode_rgba8(&path, None);
+        std::fs::remove_file(&path).unwrap();
+
+        let fitted = fitted.unwrap();
+        assert_eq!(fitted.dimensions(), (256, 128));
+        assert_eq!(fitted.get_pixel(128, 64).0, [255, 0, 0, 255]);
+        assert_eq!(own_size.unwrap().dimensions(), (100, 50));
+    }
+
+    #[test]
+    fn probes_sizes_from_headers() {
+        let png = temp_path("probe.png");
+        image::RgbaImage::new(5, 7).save(&png).unwrap();
+        let svg = temp_path("probe.svg");
+        std::fs::write(&svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="50"/>"#).unwrap();
+        let exr = temp_path("probe.exr");
+        exr::prelude::write_rgba_file(&exr, 9, 4, |_, _| (0.0, 0.0, 0.0, 1.0)).unwrap();
+        let psd_path = temp_path("probe.psd");
+        std::fs::write(&psd_path, psd(6, 3, [0, 0, 0])).unwrap();
+        let tiff_path = temp_path("probe.tiff");
+        std::fs::write(&tiff_path, tiff(&[(4, 3, 0), (2, 2, 0)])).unwrap();
+
+        let settings = LoadSettings {
+            svg_dpi: 192.0,
+            ..LoadSettings::from_config(&crate::config::Config::default())
+        };
+        let paths = [&png, &svg, &exr, &psd_path, &tiff_path];
+        let sizes: Vec<_> = paths.iter().map(|path| probe_size(path, &settings)).collect();
+        for path in paths {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        let sizes: Vec<_> = sizes.into_iter().map(Result::unwrap).collect();
+        // vector images load at the configured resolution, and files with
+        // several pages at the size of the first
+        assert_eq!(sizes, [[5, 7], [200, 100], [9, 4], [6, 3], [4, 3]]);
+    }
+
+    /// An uncompressed little endian grayscale TIFF with a page for each
+    /// of `pages`, given as width, height and the value every pixel has.
+    fn tiff(pages: &[(u32, u32, u8)]) -> Vec<u8> {
+        let mut data = b"II*\0\0\0\0\0".to_vec();
+        let mut strips = Vec::new();
+        for &(width, height, value) in pages {
+            strips.push(data.len() as u32);
+            data.extend(std::iter::repeat_n(value, (width * height) as usize));
+        }
+
+        let mut next = 4;
+        for (&(width, height, _), strip) in pages.iter().zip(strips) {
+            let ifd = data.len() as u32;
+            data[next..next + 4].copy_from_slice(&ifd.to_le_bytes());
+            let entries: [(u16, u16, u32); 9] = [
+                (256, 4, width), (257, 4, height), (258, 3, 8), (259, 3, 1), (262, 3, 1),
+                (273, 4, strip), (277, 3, 1), (278, 4, height), (279, 4, width * height),
+            ];
+            data.extend((entries.len() as u16).to_le_bytes());
+            for (tag, kind, value) in entries {
+                data.extend(tag.to_le_bytes());
+                data.extend(kind.to_le_bytes());
+                data.extend(1u32.to_le_bytes());
+                data.extend(value.to_le_bytes());
+            }
+            next = data.len();
+            data.extend(0u32.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn decodes_the_first_page_of_a_tiff() {
+        let path = temp_path("pages.tiff");
+        std::fs::write(&path, tiff(&[(4, 3, 200), (2, 2, 50)])).unwrap();
+        let img = decode_rgba8(&path, None);
+        let second = decode_file(&path, 1, 96.0);
+        std::fs::remove_file(&path).unwrap();
+
+        let img = img.unwrap();
+        assert_eq!(img.dimensions(), (4, 3));
+        assert_eq!(img.get_pixel(3, 2).0, [200, 200, 200, 255]);
+
+        let second = second.unwrap();
+        assert_eq!(second.page_count, 2);
+        assert_eq!(second.decoded.pixels.dimensions(), (2, 2));
+    }
+
+    /// An 8 bit RGB document with no resources or layers, just the
+    /// composite as raw planes, each filled with its value of `rgb`.
+    fn psd(width: u32, height: u32, rgb: [u8; 3]) -> Vec<u8> {
+        let mut data = b"8BPS".to_vec();
+        data.extend(1u16.to_be_bytes());
+        data.extend([0; 6]);
+        data.extend(3u16.to_be_bytes());
+        data.extend(height.to_be_bytes());
+        data.extend(width.to_be_bytes());
+        data.extend(8u16.to_be_bytes());
+        data.extend(3u16.to_be_bytes());
+        data.extend([0; 12]);
+        data.extend(0u16.to_be_bytes());
+        for value in rgb {
+            data.extend(std::iter::repeat_n(value, (width * height) as usize));
+        }
+        data
+    }
+
+    #[test]
+    fn decodes_the_psd_composite() {
+        let path = temp_path("composite.psd");
+        std::fs::write(&path, psd(3, 2, [10, 20, 30])).unwrap();
+        let img = decode_rgba8(&path, None);
+        std::fs::remove_file(&path).unwrap();
+
+        let img = img.unwrap();
+        assert_eq!(img.dimensions(), (3, 2));
+        assert_eq!(img.get_pixel(1, 1).0, [10, 20, 30, 255]);
+    }
+}
@@ -0,0 +1,282 @@
+use std::path::{Path, PathBuf};
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+
+use crate::image_list::FileSignature;
+
+/// The two sizes of the freedesktop.org Thumbnail Managing Standard, each
+/// with its own directory under `~/.cache/thumbnails`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flavor {
+    Normal,
+    Large,
+}
+
+impl Flavor {
+    pub const ALL: [Flavor; 2] = [Flavor::Normal, Flavor::Large];
+
+    pub fn size(self) -> u32 {
+        match self {
+            Flavor::Normal => 128,
+            Flavor::Large => 256,
+        }
+    }
+
+    fn dir_name(self) -> &'static str {
+        match self {
+            Flavor::Normal => "normal",
+            Flavor::Large => "large",
+        }
+    }
+}
+
+// what GLib leaves unescaped in file URIs, so our cache keys match theirs
+const URI_ESCAPED: &AsciiSet = &CONTROLS
+    .add(b' ').add(b'"').add(b'#').add(b'%').add(b'<').add(b'>').add(b'?')
+    .add(b'[').add(b'\\').add(b']').add(b'^').add(b'`').add(b'{').add(b'|').add(b'}');
+
+pub fn cache_dir() -> Option<PathBuf> {
+    Some(dirs::cache_dir()?.join("thumbnails"))
+}
+
+/// The `file://` URI for `path`, which thumbnails are keyed by.
+pub fn file_uri(path: &Path) -> Option<String> {
+    let path = std::fs::canonicalize(path).ok()?;
+    let path = path.to_str()?;
+    // Windows paths need a slash in front of the drive letter
+    let slash = if path.starts_with('/') { "" } else { "/" };
+    Some(format!("file://{}{}", slash, utf8_percent_encode(&path.replace('\\', "/"), URI_ESCAPED)))
+}
+
+fn thumbnail_path(dir: &Path, flavor: Flavor, uri: &str) -> PathBuf {
+    dir.join(flavor.dir_name()).join(format!("{:x}.png", md5::compute(uri)))
+}
+
+/// Reads and writes thumbnails in the shared cache, so ones made by file
+/// managers get used here and the other way around.
+#[derive(Debug, Clone)]
+pub struct ThumbnailCache {
+    dir: PathBuf,
+}
+
+impl ThumbnailCache {
+    pub fn new() -> Option<ThumbnailCache> {
+        Some(ThumbnailCache { dir: cache_dir()? })
+    }
+
+    /// The cached thumbnail for `path`, if there is one and it was made
+    /// from the file as it is now.
+    pub fn load(&self, path: &Path, sig: &FileSignature, flavor: Flavor) -> Option<image::RgbaImage> {
+        let uri = file_uri(path)?;
+        let mtime = sig.mtime()?;
+
+        let data = std::fs::read(thumbnail_path(&self.dir, flavor, &uri)).ok()?;
+        let text = png_text_chunks(&data)?;
+        let value = |key: &str| text.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+        if value("Thumb::URI") != Some(&uri) || value("Thumb::MTime") != Some(&mtime.to_string()) {
+            return None;
+        }
+
+        let img = image::load_from_memory_with_format(&data, image::ImageFormat::Png).ok()?;
+        Some(img.into_rgba8())
+    }
+
+    pub fn save(&self, path: &Path, sig: &FileSignature, flavor: Flavor, thumbnail: &image::RgbaImage)
+        -> Result<(), Box<dyn std::error::Error>>
+    {
+        let uri = file_uri(path).ok_or("no URI for path")?;
+        let mtime = sig.mtime().ok_or("no modification time")?;
+
+        let mut data = Vec::new();
+        let (width, height) = thumbnail.dimensions();
+        image::png::PngEncoder::new(&mut data)
+            .encode(thumbnail, width, height, image::ColorType::Rgba8)?;
+
+        let data = insert_png_text_chunks(&data, &[
+            ("Thumb::URI", &uri),
+            ("Thumb::MTime", &mtime.to_string()),
+            ("Thumb::Size", &sig.len.to_string()),
+            ("Software", env!("CARGO_PKG_NAME")),
+        ])?;
+
+        let target = thumbnail_path(&self.dir, flavor, &uri);
+        let dir = target.parent().ok_or("no cache directory")?;
+        create_private_dir(dir)?;
+
+        // written under another name first so nobody reads half a file
+        let temp = dir.join(format!("{}.{}.tmp", target.file_name().unwrap().to_string_lossy(),
+            std::process::id()));
+        write_private_file(&temp, &data)?;
+        std::fs::rename(&temp, &target)?;
+        Ok(())
+    }
+}
+
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir)
+}
+
+fn write_private_file(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(data)
+}
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Splits PNG data into (type, data) chunks. `None` if it isn't a PNG or
+/// is cut short.
+fn png_chunks(data: &[u8]) -> Option<Vec<([u8; 4], &[u8])>> {
+    let mut rest = data.strip_prefix(&PNG_SIGNATURE)?;
+    let mut chunks = Vec::new();
+
+    while !rest.is_empty() {
+        let len = u32::from_be_bytes(rest.get(0..4)?.try_into().ok()?) as usize;
+        let kind: [u8; 4] = rest.get(4..8)?.try_into().ok()?;
+        let chunk = rest.get(8..8 + len)?;
+        chunks.push((kind, chunk));
+        // skip the CRC
+        rest = rest.get(8 + len + 4..)?;
+    }
+
+    Some(chunks)
+}
+
+/// The key/value pairs from the `tEXt` chunks of PNG data.
+fn png_text_chunks(data: &[u8]) -> Option<Vec<(String, String)>> {
+    let text = png_chunks(data)?.into_iter()
+        .filter(|(kind, _)| kind == b"tEXt")
+        .filter_map(|(_, chunk)| {
+            let split = chunk.iter().position(|&b| b == 0)?;
+            // tEXt is Latin-1
+            let latin1 = |bytes: &[u8]| bytes.iter().map(|&b| b as char).collect::<String>();
+            Some((latin1(&chunk[..split]), latin1(&chunk[split + 1..])))
+        })
+        .collect();
+    Some(text)
+}
+
+/// Adds `tEXt` chunks straight after the header of PNG data.
+fn insert_png_text_chunks(data: &[u8], text: &[(&str, &str)])
+    -> Result<Vec<u8>, Box<dyn std::error::Error>>
+{
+    // the header chunk is always first and always the same length
+    const HEADER_END: usize = 8 + 4 + 4 + 13 + 4;
+    if png_chunks(data).and_then(|chunks| chunks.first().map(|(kind, _)| *kind)) != Some(*b"IHDR") {
+        return Err("not a PNG".into());
+    }
+
+    let mut out = data[..HEADER_END].to_vec();
+    for (key, value) in text {
+        let mut chunk = b"tEXt".to_vec();
+        chunk.extend(key.bytes());
+        chunk.push(0);
+        // URIs are escaped down to ASCII, which is also Latin-1
+        chunk.extend(value.bytes());
+
+        out.extend(((chunk.len() - 4) as u32).to_be_bytes());
+        out.extend(&chunk);
+        out.extend(crc32fast::hash(&chunk).to_be_bytes());
+    }
+    out.extend(&data[HEADER_END..]);
+    Ok(out)
+}
+
+#[derive(Debug, Default)]
+pub struct PruneSummary {
+    pub removed: u64,
+    pub kept: u64,
+    pub bytes_freed: u64,
+}
+
+/// Removes cached thumbnails whose source file is gone or has changed
+/// since, and any not touched in `max_age` if that's given. Only looks at
+/// thumbnails of local files; everything else is left alone.
+pub fn prune(max_age: Option<std::time::Duration>, dry_run: bool)
+    -> Result<PruneSummary, Box<dyn std::error::Error>>
+{
+    let dir = cache_dir().ok_or("no cache directory")?;
+    let mut summary = PruneSummary::default();
+    let now = std::time::SystemTime::now();
+
+    for flavor in Flavor::ALL {
+        let entries = match std::fs::read_dir(dir.join(flavor.dir_name())) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("png") {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+
+            let stale = match std::fs::read(&path).ok().as_deref().and_then(png_text_chunks) {
+                Some(text) => is_stale(&text),
+                None => true,
+            };
+            let old = max_age.is_some_and(|max_age| {
+                metadata.modified().ok()
+                    .and_then(|modified| now.duration_since(modified).ok())
+                    .is_some_and(|age| age > max_age)
+            });
+
+            if stale || old {
+                if !dry_run {
+                    std::fs::remove_file(&path)?;
+                }
+                summary.removed += 1;
+                summary.bytes_freed += metadata.len();
+            } else {
+                summary.kept += 1;
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+fn is_stale(text: &[(String, String)]) -> bool {
+    let value = |key: &str| text.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+    let path = match value("Thumb::URI").and_then(|uri| uri.strip_prefix("file://")) {
+        Some(path) => percent_encoding::percent_decode_str(path).decode_utf8_lossy().into_owned(),
+        // a thumbnail of something that isn't a local file, or not ours to judge
+        None => return false,
+    };
+
+    match FileSignature::new(&path).ok().and_then(|sig| sig.mtime()) {
+        Some(mtime) => value("Thumb::MTime") != Some(&mtime.to_string()),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn file_uris_are_percent_encoded() {
+        let dir = std::env::temp_dir().join(format!("viewer-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a b#1?%\u{e9}.png");
+        std::fs::write(&path, b"").unwrap();
+        let uri = file_uri(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let uri = uri.unwrap();
+        assert!(uri.starts_with("file:///"), "{}", uri);
+        assert!(uri.ends_with("/a%20b%231%3F%25%C3%A9.png"), "{}", uri);
+    }
+}
@@ -0,0 +1,244 @@
+use std::path::PathBuf;
+
+/// A window size with an optional position, written the X11 way as
+/// `WxH+X+Y`. A `-` offset counts from the right or bottom edge of the
+/// monitor to the same edge of the window, so `800x600-0+0` is the top
+/// right corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    pub size: [u32; 2],
+    pub position: Option<[Offset; 2]>,
+}
+
+/// One coordinate of a window position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Offset {
+    /// `+N`: from the left or top of the monitor.
+    Start(i32),
+    /// `-N`: from the right or bottom of the monitor.
+    End(i32),
+}
+
+impl Offset {
+    fn resolve(self, window: u32, monitor: u32) -> i32 {
+        match self {
+            Offset::Start(n) => n,
+            Offset::End(n) => monitor as i32 - window as i32 - n,
+        }
+    }
+}
+
+impl Geometry {
+    pub fn of_window(window: &glutin::window::Window) -> Geometry {
+        let size = window.inner_size();
+        let position = window.outer_position().ok()
+            .map(|p| [Offset::Start(p.x), Offset::Start(p.y)]);
+        Geometry { size: [size.width, size.height], position }
+    }
+
+    /// Where the window's top left corner goes on a monitor of the given
+    /// size. Offsets from the far edges need to know the monitor.
+    pub fn position_on(&self, monitor_size: Option<[u32; 2]>) -> Option<[i32; 2]> {
+        let [x, y] = self.position?;
+        match (x, y, monitor_size) {
+            (Offset::Start(x), Offset::Start(y), _) => Some([x, y]),
+            (_, _, Some(m)) => Some([x.resolve(self.size[0], m[0]), y.resolve(self.size[1], m[1])]),
+            (_, _, None) => None,
+        }
+    }
+
+    pub fn apply(
+        &self,
+        wb: glutin::window::WindowBuilder,
+        monitor_size: Option<[u32; 2]>,
+    ) -> glutin::window::WindowBuilder {
+        use glutin::dpi::{PhysicalPosition, PhysicalSize};
+
+        let wb = wb.with_inner_size(PhysicalSize::new(self.size[0], self.size[1]));
+        match self.position_on(monitor_size) {
+            Some([x, y]) => wb.with_position(PhysicalPosition::new(x, y)),
+            None => wb,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseGeometryError(String);
+
+impl std::fmt::Display for ParseGeometryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "invalid geometry {:?}, expected WxH or WxH+X+Y", self.0)
+    }
+}
+
+impl std::error::Error for ParseGeometryError {}
+
+impl std::str::FromStr for Geometry {
+    type Err = ParseGeometryError;
+
+    fn from_str(s: &str) -> Result<Geometry, ParseGeometryError> {
+        let err = || ParseGeometryError(s.to_owned());
+
+        let (size, offsets) = match s.find(['+', '-']) {
+            Some(i) => s.split_at(i),
+            None => (s, ""),
+        };
+
+        let (w, h) = size.split_once(['x', 'X']).ok_or_else(err)?;
+        let w: u32 = w.parse().map_err(|_| err())?;
+        let h: u32 = h.parse().map_err(|_| err())?;
+        if w == 0 || h == 0 {
+            return Err(err());
+        }
+
+        let position = if offsets.is_empty() {
+            None
+        } else {
+            let (x, rest) = parse_offset(offsets).ok_or_else(err)?;
+            let (y, rest) = parse_offset(rest).ok_or_else(err)?;
+            if !rest.is_empty() {
+                return Err(err());
+            }
+            Some([x, y])
+        };
+
+        Ok(Geometry { size: [w, h], position })
+    }
+}
+
+/// Splits one `+N` or `-N` off the front of `s`. As in X11, the number
+/// itself may carry a sign too, e.g. `+-10` for a window hanging off the
+/// left of the monitor.
+fn parse_offset(s: &str) -> Option<(Offset, &str)> {
+    let from_end = match s.as_bytes().first()? {
+        b'+' => false,
+        b'-' => true,
+        _ => return None,
+    };
+    let s = &s[1..];
+    let sign = if s.starts_with(['+', '-']) { 1 } else { 0 };
+    let end = s[sign..].find(['+', '-']).map_or(s.len(), |i| i + sign);
+    let n: i32 = s[..end].parse().ok()?;
+    let offset = if from_end { Offset::End(n) } else { Offset::Start(n) };
+    Some((offset, &s[end..]))
+}
+
+impl std::fmt::Display for Offset {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Offset::Start(n) => write!(f, "+{}", n),
+            Offset::End(n) => write!(f, "-{}", n),
+        }
+    }
+}
+
+impl std::fmt::Display for Geometry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}x{}", self.size[0], self.size[1])?;
+        if let Some([x, y]) = self.position {
+            write!(f, "{}{}", x, y)?;
+        }
+        Ok(())
+    }
+}
+
+/// Scales `image_size` down (never up) so it fits within 90% of the monitor,
+/// leaving a little room for window decorations and task bars.
+pub fn fit_to_monitor(image_size: [u32; 2], monitor_size: [u32; 2]) -> [u32; 2] {
+    let max_w = monitor_size[0] as f32 * 0.9;
+    let max_h = monitor_size[1] as f32 * 0.9;
+    let [w, h] = [image_size[0] as f32, image_size[1] as f32];
+
+    let scale = (max_w / w).min(max_h / h).min(1.0);
+    [((w * scale) as u32).max(1), ((h * scale) as u32).max(1)]
+}
+
+fn saved_geometry_path() -> Option<PathBuf> {
+    let dir = dirs::state_dir().or_else(dirs::data_local_dir)?;
+    Some(dir.join("viewer").join("geometry"))
+}
+
+/// The window geometry from the end of the previous run, if there is one.
+pub fn load_saved() -> Option<Geometry> {
+    let text = std::fs::read_to_string(saved_geometry_path()?).ok()?;
+    text.trim().parse().ok()
+}
+
+pub fn save(geometry: Geometry) -> Result<(), Box<dyn std::error::Error>> {
+    let path = saved_geometry_path().ok_or("no state directory available")?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, format!("{}\n", geometry))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Option<Geometry> {
+        s.parse().ok()
+    }
+
+    #[test]
+    fn parses_sizes_and_offsets() {
+        assert_eq!(parse("800x600"), Some(Geometry { size: [800, 600], position: None }));
+        assert_eq!(parse("800X600"), Some(Geometry { size: [800, 600], position: None }));
+        assert_eq!(
+            parse("800x600+10+20").unwrap().position,
+            Some([Offset::Start(10), Offset::Start(20)]),
+        );
+        assert_eq!(
+            parse("800x600-10+20").unwrap().position,
+            Some([Offset::End(10), Offset::Start(20)]),
+        );
+        assert_eq!(
+            parse("800x600+10-0").unwrap().position,
+            Some([Offset::Start(10), Offset::End(0)]),
+        );
+        assert_eq!(
+            parse("800x600+-10--5").unwrap().position,
+            Some([Offset::Start(-10), Offset::End(-5)]),
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_geometry() {
+        for s in [
+            "", "0x0", "0x600", "800x0", "800", "800x", "x600", "800x600+",
+            "800x600+10", "800x600+10+", "800x600+10+20+30", "800x600+-+10+20",
+            "800x600+a+20", "-800x600", "800y600", "800x600 ",
+        ] {
+            assert_eq!(parse(s), None, "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for s in ["800x600", "800x600+10+20", "800x600-10+20", "800x600+0-0", "800x600+-10--5"] {
+            assert_eq!(parse(s).unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn negative_offsets_count_from_the_far_edge() {
+        let monitor = Some([1920, 1080]);
+        assert_eq!(parse("800x600+10+20").unwrap().position_on(None), Some([10, 20]));
+        assert_eq!(parse("800x600-0-0").unwrap().position_on(monitor), Some([1120, 480]));
+        assert_eq!(parse("800x600-10+20").unwrap().position_on(monitor), Some([1110, 20]));
+        assert_eq!(parse("800x600-10+20").unwrap().position_on(None), None);
+        assert_eq!(parse("800x600").unwrap().position_on(monitor), None);
+    }
+
+    #[test]
+    fn fits_images_to_the_monitor() {
+        // small images keep their size
+        assert_eq!(fit_to_monitor([640, 480], [1920, 1080]), [640, 480]);
+        // large ones shrink to 90% of the tighter dimension, keeping their shape
+        assert_eq!(fit_to_monitor([4000, 1000], [2000, 1000]), [1800, 450]);
+        assert_eq!(fit_to_monitor([1000, 4000], [2000, 1000]), [225, 900]);
+        // and never vanish
+        assert_eq!(fit_to_monitor([100000, 1], [1000, 1000])[1], 1);
+    }
+}